@@ -0,0 +1,50 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use chip_8_emulator::chip::chip8::{quirks::Quirks, variant::Variant, Chip8};
+use chip_8_emulator::chip::Chip;
+
+/// The number of cycles executed per iteration of a benchmark.
+const CYCLES: u64 = 100_000;
+
+/// A loop of register arithmetic and conditional skips.
+const ALU_LOOP: [u8; 18] = [
+    0x70, 0x01, // 0x200: ADD V0, 0x01
+    0x81, 0x04, // 0x202: ADD V1, V0
+    0x82, 0x13, // 0x204: XOR V2, V1
+    0x40, 0x00, // 0x206: SNE V0, 0x00
+    0x73, 0x01, // 0x208: ADD V3, 0x01
+    0x84, 0x26, // 0x20A: SHR V4, V2
+    0x90, 0x10, // 0x20C: SNE V0, V1
+    0x75, 0x01, // 0x20E: ADD V5, 0x01
+    0x12, 0x00, // 0x210: JP 0x200
+];
+
+/// A loop drawing the font sprite of `0` at changing positions.
+const DRAW_LOOP: [u8; 10] = [
+    0x70, 0x01, // 0x200: ADD V0, 0x01
+    0x71, 0x03, // 0x202: ADD V1, 0x03
+    0xA0, 0x00, // 0x204: LD I, 0x000
+    0xD0, 0x15, // 0x206: DRW V0, V1, 5
+    0x12, 0x00, // 0x208: JP 0x200
+];
+
+/// Measures the number of cycles executed per second for each of the programs.
+fn bench_cycle(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cycle");
+    group.throughput(Throughput::Elements(CYCLES));
+    for (name, program) in &[("alu", &ALU_LOOP[..]), ("draw", &DRAW_LOOP[..])] {
+        let mut chip8 = Chip8::with_seed(Variant::Chip8, Quirks::default(), 0);
+        chip8.load_program(program).unwrap();
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                for _ in 0..CYCLES {
+                    black_box(chip8.cycle()).unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_cycle);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use chip_8_emulator::chip::chip8::assembler::{assemble_file, AssemblerError};
+use chip_8_emulator::chip::chip8::Chip8;
+use chip_8_emulator::chip::{Chip, LoadProgramError};
+
+/// The address at which programs are loaded by the emulator.
+const BASE_ADDRESS: u16 = 0x200;
+
+/// Error type for errors that occur during parsing the command line arguments,
+/// assembling the program, and writing the resulting program.
+enum Error {
+    InvalidUsage(String),
+    AssemblerError(AssemblerError),
+    LoadProgramError(LoadProgramError),
+    CouldNotWriteFile(String),
+}
+
+/// Parses the command line arguments, which consist of the path to the source
+/// and the path of the program to write.
+fn parse_args() -> Result<(String, String), Error> {
+    let args: Vec<String> = env::args().collect();
+    match args.len() {
+        3 => Ok((args[1].clone(), args[2].clone())),
+        _ => Err(Error::InvalidUsage(
+            "chip8-asm <path-to-source> <path-to-program>".to_string(),
+        )),
+    }
+}
+
+/// Assembles the source given in the command line arguments and writes the
+/// resulting program.
+fn run() -> Result<(), Error> {
+    let (source_path, program_path) = parse_args()?;
+    let program =
+        assemble_file(Path::new(&source_path), BASE_ADDRESS).map_err(Error::AssemblerError)?;
+
+    // make sure the emulator accepts the resulting program
+    let mut chip8 = Chip8::default();
+    chip8
+        .load_program(&program)
+        .map_err(Error::LoadProgramError)?;
+
+    fs::write(&program_path, &program).map_err(|_| Error::CouldNotWriteFile(program_path))
+}
+
+fn main() {
+    if let Err(e) = run() {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidUsage(message) => write!(f, "Usage: {}", message),
+            Error::AssemblerError(e) => write!(f, "{}", e),
+            Error::LoadProgramError(e) => write!(f, "{}", e),
+            Error::CouldNotWriteFile(message) => {
+                write!(f, "Could not write file: {:?}", message)
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::env;
+use std::fs;
+
+use chip_8_emulator::chip::chip8::disassembler::{
+    disassemble_at, reachable_addresses, DisassembledInstruction,
+};
+
+/// The address at which programs are loaded if no base address is given.
+const DEFAULT_BASE_ADDRESS: u16 = 0x200;
+
+/// Error type for errors that occur during parsing the command line arguments
+/// and loading the program based on the arguments.
+enum Error {
+    InvalidUsage(String),
+    InvalidBaseAddress(String),
+    CouldNotReadFile(String),
+}
+
+/// Represents a line of the listing.
+enum Line {
+    /// An instruction that is reachable from the entry point.
+    Reachable(DisassembledInstruction),
+
+    /// An instruction that is not reachable from the entry point.
+    Unreachable(DisassembledInstruction),
+
+    /// Bytes that do not represent a supported instruction and are not
+    /// reachable from the entry point.
+    Data(DisassembledInstruction),
+}
+
+/// Parses the command line arguments, which consist of the path to the program
+/// and an optional base address (defaulting to `0x200`).
+fn parse_args() -> Result<(String, u16), Error> {
+    let args: Vec<String> = env::args().collect();
+    match args.len() {
+        2 => Ok((args[1].clone(), DEFAULT_BASE_ADDRESS)),
+        3 => {
+            let base_address = u16::from_str_radix(args[2].trim_start_matches("0x"), 16)
+                .map_err(|_| Error::InvalidBaseAddress(args[2].clone()))?;
+            Ok((args[1].clone(), base_address))
+        }
+        _ => Err(Error::InvalidUsage(
+            "chip8-disasm <path-to-program> [base-address-in-hex]".to_string(),
+        )),
+    }
+}
+
+/// Builds the listing of the given program. Instructions reachable from the entry
+/// point are disassembled following the control flow, whereas the gaps between
+/// them are disassembled linearly and flagged as unreachable or data.
+fn listing(bytes: &[u8], base_address: u16) -> Vec<Line> {
+    let reachable = reachable_addresses(bytes, base_address);
+
+    let mut lines = vec![];
+    let mut address = base_address;
+    while let Some(instruction) = disassemble_at(bytes, base_address, address) {
+        if reachable.contains(&address) {
+            address = address.wrapping_add(instruction.size());
+            lines.push(Line::Reachable(instruction));
+            continue;
+        }
+
+        // do not let instructions in a gap overlap the next reachable instruction
+        let gap_end = reachable
+            .range(address..)
+            .next()
+            .map(|next| next - address)
+            .unwrap_or(u16::MAX);
+        let mut instruction = instruction;
+        if instruction.size() > gap_end {
+            instruction.bytes.truncate(gap_end as usize);
+            instruction.mnemonic = None;
+        }
+
+        address = address.wrapping_add(instruction.size());
+        if instruction.mnemonic.is_some() {
+            lines.push(Line::Unreachable(instruction));
+        } else {
+            lines.push(Line::Data(instruction));
+        }
+    }
+    lines
+}
+
+/// Prints the listing of the program given in the command line arguments.
+fn main() {
+    let result = parse_args().and_then(|(path, base_address)| {
+        let bytes = fs::read(&path).map_err(|_| Error::CouldNotReadFile(path))?;
+        Ok((bytes, base_address))
+    });
+
+    let (bytes, base_address) = match result {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    for line in listing(&bytes, base_address) {
+        println!("{}", line);
+    }
+}
+
+impl std::fmt::Display for Line {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (instruction, text, flag) = match self {
+            Line::Reachable(instruction) => match &instruction.mnemonic {
+                Some(mnemonic) => (instruction, mnemonic.clone(), ""),
+                None => (instruction, "???".to_string(), "; invalid opcode"),
+            },
+            Line::Unreachable(instruction) => (
+                instruction,
+                instruction.mnemonic.clone().unwrap_or_default(),
+                "; unreachable",
+            ),
+            Line::Data(instruction) => {
+                let bytes: Vec<String> = instruction
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:#04X}", byte))
+                    .collect();
+                (instruction, format!("DB {}", bytes.join(", ")), "; data")
+            }
+        };
+        let raw: String = instruction
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let line = format!(
+            "{:#06X}  {:<8}  {:<24}{}",
+            instruction.address, raw, text, flag
+        );
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidUsage(message) => write!(f, "Usage: {}", message),
+            Error::InvalidBaseAddress(message) => {
+                write!(f, "Invalid base address: {:?}", message)
+            }
+            Error::CouldNotReadFile(message) => write!(f, "Could not read file: {:?}", message),
+        }
+    }
+}
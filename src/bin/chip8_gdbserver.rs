@@ -0,0 +1,66 @@
+use std::env;
+use std::fs;
+use std::net::TcpListener;
+
+use chip_8_emulator::chip::chip8::gdb_stub::serve;
+use chip_8_emulator::chip::chip8::Chip8;
+use chip_8_emulator::chip::{Chip, LoadProgramError};
+
+/// The port listened on if none is given.
+const DEFAULT_PORT: u16 = 1234;
+
+/// Error type for errors that occur during parsing the command line arguments,
+/// loading the program, and serving the debugger.
+enum Error {
+    InvalidUsage(String),
+    CouldNotReadFile(String),
+    LoadProgramError(LoadProgramError),
+    CouldNotServe(std::io::Error),
+}
+
+/// Parses the command line arguments, which consist of the path to the program
+/// and optionally the port to listen on.
+fn parse_args() -> Result<(String, u16), Error> {
+    let usage =
+        || Error::InvalidUsage("chip8-gdbserver <path-to-chip-8-program> [port]".to_string());
+    let args: Vec<String> = env::args().collect();
+    match args.len() {
+        2 => Ok((args[1].clone(), DEFAULT_PORT)),
+        3 => Ok((args[1].clone(), args[2].parse().map_err(|_| usage())?)),
+        _ => Err(usage()),
+    }
+}
+
+/// Loads the program given in the command line arguments and serves a single
+/// debugger connecting to the local port.
+fn run() -> Result<(), Error> {
+    let (program_path, port) = parse_args()?;
+    let program = fs::read(&program_path).map_err(|_| Error::CouldNotReadFile(program_path))?;
+    let mut chip8 = Chip8::default();
+    chip8
+        .load_program(&program)
+        .map_err(Error::LoadProgramError)?;
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(Error::CouldNotServe)?;
+    println!("Listening on 127.0.0.1:{}", port);
+    let (stream, _) = listener.accept().map_err(Error::CouldNotServe)?;
+    serve(&mut chip8, stream).map_err(Error::CouldNotServe)
+}
+
+fn main() {
+    if let Err(e) = run() {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidUsage(message) => write!(f, "Usage: {}", message),
+            Error::CouldNotReadFile(message) => write!(f, "Could not read file: {:?}", message),
+            Error::LoadProgramError(e) => write!(f, "{}", e),
+            Error::CouldNotServe(e) => write!(f, "Could not serve debugger: {}", e),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+
+use chip_8_emulator::chip::audio::{WavSink, DEFAULT_SAMPLE_RATE};
+use chip_8_emulator::chip::chip8::quirks::Quirks;
+use chip_8_emulator::chip::chip8::variant::Variant;
+use chip_8_emulator::chip::chip8::Chip8;
+use chip_8_emulator::chip::movie::{LoadMovieError, Movie, ReplayError};
+use chip_8_emulator::chip::scheduler::{Scheduler, DEFAULT_INSTRUCTIONS_PER_FRAME};
+use chip_8_emulator::chip::{Chip, ExecutionError, LoadProgramError};
+
+/// The number of cycles executed if no limit is given.
+const DEFAULT_CYCLES: u64 = 100_000;
+
+/// The characters used to print pixels in ASCII, indexed by the bitplanes that are
+/// set for a pixel. Bit `i` of the index corresponds to bitplane `i`.
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+/// Error type for errors that occur during parsing the command line arguments,
+/// loading the program and the input timeline, running the program, and writing
+/// the dumps.
+enum Error {
+    InvalidUsage(String),
+    InvalidInput(String),
+    CouldNotReadFile(String),
+    CouldNotWriteFile(String),
+    InvalidProgram(LoadProgramError),
+    InvalidMovie(LoadMovieError),
+    ExecutionError(ExecutionError),
+    ReplayError(ReplayError),
+}
+
+/// The options given in the command line arguments.
+struct Options {
+    program_path: String,
+    cycles: u64,
+    instructions_per_frame: u32,
+    until_pc: Option<u16>,
+    seed: Option<u64>,
+    input_path: Option<String>,
+    replay_path: Option<String>,
+    image_path: Option<String>,
+    memory_path: Option<String>,
+    audio_path: Option<String>,
+}
+
+/// A change of an input pin scheduled for the given cycle.
+struct InputEvent {
+    cycle: u64,
+    pin: u8,
+    value: bool,
+}
+
+/// Why the execution stopped.
+enum Stop {
+    CycleLimit,
+    ProgramCounter(u16),
+    Exited,
+    Replayed,
+}
+
+/// Parses the command line arguments, which consist of the path to the program
+/// followed by the options.
+fn parse_args() -> Result<Options, Error> {
+    let usage = || {
+        Error::InvalidUsage(
+            "chip8-headless <path-to-program> [--cycles <n>] [--ipf <n>] [--until-pc <address>] [--seed <n>] [--input <path-to-timeline> | --replay <path-to-movie>] [--image <path-to-pgm>] [--memory <path-to-dump>] [--audio <path-to-wav>]"
+                .to_string(),
+        )
+    };
+
+    let mut args = env::args().skip(1);
+    let mut options = Options {
+        program_path: args.next().ok_or_else(usage)?,
+        cycles: DEFAULT_CYCLES,
+        instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+        until_pc: None,
+        seed: None,
+        input_path: None,
+        replay_path: None,
+        image_path: None,
+        memory_path: None,
+        audio_path: None,
+    };
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or_else(usage)?;
+        match arg.as_str() {
+            "--cycles" => options.cycles = value.parse().map_err(|_| usage())?,
+            "--ipf" => {
+                options.instructions_per_frame = match value.parse() {
+                    Ok(instructions_per_frame) if instructions_per_frame > 0 => {
+                        instructions_per_frame
+                    }
+                    _ => return Err(usage()),
+                }
+            }
+            "--until-pc" => {
+                options.until_pc = Some(
+                    u16::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|_| usage())?,
+                )
+            }
+            "--seed" => options.seed = Some(value.parse().map_err(|_| usage())?),
+            "--input" => options.input_path = Some(value),
+            "--replay" => options.replay_path = Some(value),
+            "--image" => options.image_path = Some(value),
+            "--memory" => options.memory_path = Some(value),
+            "--audio" => options.audio_path = Some(value),
+            _ => return Err(usage()),
+        }
+    }
+    if options.input_path.is_some() && options.replay_path.is_some() {
+        return Err(usage());
+    }
+    Ok(options)
+}
+
+/// Parses an input timeline. Each line consists of a cycle, a key in hex, and
+/// either `down` or `up` (e.g., `120 A down`). Empty lines and lines starting
+/// with `#` are ignored. The events are returned ordered by cycle.
+fn parse_input(timeline: &str) -> Result<Vec<InputEvent>, Error> {
+    let mut events = vec![];
+    for (number, line) in timeline.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || Error::InvalidInput(format!("line {}: {:?}", number + 1, line));
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (cycle, pin, value) = match parts.as_slice() {
+            [cycle, pin, value] => (cycle, pin, value),
+            _ => return Err(invalid()),
+        };
+        events.push(InputEvent {
+            cycle: cycle.parse().map_err(|_| invalid())?,
+            pin: match u8::from_str_radix(pin, 16) {
+                Ok(pin) if pin <= 0xF => pin,
+                _ => return Err(invalid()),
+            },
+            value: match *value {
+                "down" => true,
+                "up" => false,
+                _ => return Err(invalid()),
+            },
+        });
+    }
+    events.sort_by_key(|event| event.cycle);
+    Ok(events)
+}
+
+/// Runs `chip8` until the cycle limit is reached, the program counter reaches
+/// the given address, or the program exits. The timers are ticked once every
+/// `instructions_per_frame` cycles given in `options`, without limiting the speed
+/// of the execution. Returns why the execution stopped along with the number of
+/// executed cycles.
+fn run_program(
+    chip8: &mut Chip8,
+    options: &Options,
+    input: &[InputEvent],
+) -> (Result<Stop, ExecutionError>, u64) {
+    let mut scheduler = Scheduler::new(options.instructions_per_frame);
+    let mut pending = input.iter().peekable();
+    for cycle in 0..options.cycles {
+        if chip8.has_exited() {
+            return (Ok(Stop::Exited), cycle);
+        }
+        if options.until_pc == Some(chip8.program_counter()) {
+            return (Ok(Stop::ProgramCounter(chip8.program_counter())), cycle);
+        }
+        while let Some(event) = pending.next_if(|event| event.cycle <= cycle) {
+            chip8.set_input_pin(event.pin, event.value);
+        }
+        if let Err(e) = scheduler.cycle(chip8) {
+            return (Err(e), cycle);
+        }
+    }
+    if chip8.has_exited() {
+        return (Ok(Stop::Exited), options.cycles);
+    }
+    (Ok(Stop::CycleLimit), options.cycles)
+}
+
+/// Formats the display, using the characters in `ASCII_PIXELS`.
+fn format_display(chip8: &Chip8) -> String {
+    let framebuffer = chip8.framebuffer();
+    framebuffer
+        .pixels()
+        .chunks(framebuffer.width())
+        .map(|row| {
+            row.iter()
+                .map(|level| ASCII_PIXELS[*level as usize])
+                .collect()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Encodes the display as a binary PGM image, where pixels that are set in all
+/// bitplanes are white.
+fn display_image(chip8: &Chip8) -> Vec<u8> {
+    let framebuffer = chip8.framebuffer();
+    let max_level = (1 << framebuffer.planes()) - 1;
+    let mut image = format!(
+        "P5\n{} {}\n255\n",
+        framebuffer.width(),
+        framebuffer.height()
+    )
+    .into_bytes();
+    image.extend(
+        framebuffer
+            .pixels()
+            .iter()
+            .map(|level| (*level as usize * 255 / max_level) as u8),
+    );
+    image
+}
+
+/// Formats the registers, the timers and the stack.
+fn format_registers(chip8: &Chip8) -> String {
+    let registers: Vec<String> = chip8
+        .registers()
+        .iter()
+        .enumerate()
+        .map(|(i, value)| format!("V{:X}={:02X}", i, value))
+        .collect();
+    let stack: Vec<String> = chip8
+        .stack()
+        .iter()
+        .map(|address| format!("{:#06X}", address))
+        .collect();
+    let (delay, sound) = chip8.timers();
+    format!(
+        "{}\nI={:#06X} PC={:#06X} DT={:02X} ST={:02X}\nStack=[{}]",
+        registers.join(" "),
+        chip8.index(),
+        chip8.program_counter(),
+        delay,
+        sound,
+        stack.join(", ")
+    )
+}
+
+/// Loads and runs the program given in the command line arguments, or replays the
+/// given movie, and dumps the final state. The state is dumped even if the
+/// execution or the replay failed.
+fn run() -> Result<(), Error> {
+    let options = parse_args()?;
+    let program = fs::read(&options.program_path)
+        .map_err(|_| Error::CouldNotReadFile(options.program_path.clone()))?;
+    let input = match &options.input_path {
+        Some(path) => parse_input(
+            &fs::read_to_string(path).map_err(|_| Error::CouldNotReadFile(path.clone()))?,
+        )?,
+        None => vec![],
+    };
+
+    let (variant, quirks) = if options.program_path.ends_with(".xo8") {
+        (Variant::XoChip, Quirks::xo_chip())
+    } else {
+        (Variant::Chip8, Quirks::default())
+    };
+    let mut chip8 = match options.seed {
+        Some(seed) => Chip8::with_seed(variant, quirks, seed),
+        None => Chip8::with_variant(variant, quirks),
+    };
+    chip8
+        .load_program(&program)
+        .map_err(Error::InvalidProgram)?;
+    if let Some(path) = &options.audio_path {
+        let file = File::create(path).map_err(|_| Error::CouldNotWriteFile(path.clone()))?;
+        let sink = WavSink::new(BufWriter::new(file), DEFAULT_SAMPLE_RATE)
+            .map_err(|_| Error::CouldNotWriteFile(path.clone()))?;
+        chip8.set_audio_sink(Box::new(sink));
+    }
+
+    let (result, cycles) = match &options.replay_path {
+        Some(path) => {
+            let bytes = fs::read(path).map_err(|_| Error::CouldNotReadFile(path.clone()))?;
+            let movie = Movie::from_bytes(&bytes).map_err(Error::InvalidMovie)?;
+            let result = movie.replay(&mut chip8).map(|_| Stop::Replayed);
+            (result.map_err(Error::ReplayError), movie.length())
+        }
+        None => {
+            let (result, cycles) = run_program(&mut chip8, &options, &input);
+            (result.map_err(Error::ExecutionError), cycles)
+        }
+    };
+
+    println!("{}\n", format_display(&chip8));
+    println!("{}\n", format_registers(&chip8));
+    if let Some(path) = &options.image_path {
+        fs::write(path, display_image(&chip8))
+            .map_err(|_| Error::CouldNotWriteFile(path.clone()))?;
+    }
+    if let Some(path) = &options.memory_path {
+        fs::write(path, chip8.memory()).map_err(|_| Error::CouldNotWriteFile(path.clone()))?;
+    }
+    if let (Some(path), Some(mut sink)) = (&options.audio_path, chip8.take_audio_sink()) {
+        sink.finish()
+            .map_err(|_| Error::CouldNotWriteFile(path.clone()))?;
+    }
+
+    match result? {
+        Stop::CycleLimit => println!("Stopped after {} cycles", cycles),
+        Stop::ProgramCounter(address) => println!(
+            "Stopped at program counter {:#06X} after {} cycles",
+            address, cycles
+        ),
+        Stop::Exited => println!("Program exited after {} cycles", cycles),
+        Stop::Replayed => println!("Replay matches the recording after {} cycles", cycles),
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::InvalidUsage(message) => write!(f, "Usage: {}", message),
+            Error::InvalidInput(message) => write!(f, "Invalid input timeline: {}", message),
+            Error::CouldNotReadFile(message) => write!(f, "Could not read file: {:?}", message),
+            Error::CouldNotWriteFile(message) => {
+                write!(f, "Could not write file: {:?}", message)
+            }
+            Error::InvalidProgram(e) => write!(f, "{}", e),
+            Error::InvalidMovie(e) => write!(f, "{}", e),
+            Error::ExecutionError(e) => write!(f, "{}", e),
+            Error::ReplayError(e) => write!(f, "{}", e),
+        }
+    }
+}
@@ -1,48 +1,236 @@
-use crossbeam_channel::{bounded, Receiver};
-use cursive::CbSink;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use cursive::{
+    event::{Event as UiEvent, EventResult, EventTrigger, Key},
+    view::{Nameable, View},
+    views::{Dialog, OnEventView},
+    CbSink,
+};
 use std::env;
 use std::fs;
 use std::fs::File;
-use std::io::Read;
-use std::time::Duration;
+use std::io::{Read, Write};
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "audio")]
+use chip_8_emulator::chip::cpal_audio::CpalOutput;
 use chip_8_emulator::chip::{
-    chip8::cursive_display::Display, chip8::Chip8, Chip, ChipWithCursiveDisplay, LoadProgramError,
+    chip8::cursive_debugger::debugger_layout,
+    chip8::cursive_display::{theme, theme_names, Display, Palette, DISPLAY_NAME},
+    chip8::debugger::{Breakpoint, ParseError, Watchpoint},
+    chip8::keymap::{Keymap, KeymapConfig, KeymapError},
+    chip8::quirks::Quirks,
+    chip8::variant::Variant,
+    chip8::Chip8,
+    keyboard::{
+        parse_kitty_key_event, KeyEventKind, KeyTracker, DEFAULT_AUTO_RELEASE_TIMEOUT,
+        KITTY_KEYBOARD_DISABLE, KITTY_KEYBOARD_ENABLE,
+    },
+    movie::{InputEvent, LoadMovieError, Movie},
+    rewind::RewindBuffer,
+    scheduler::{Scheduler, DEFAULT_INSTRUCTIONS_PER_FRAME},
+    Chip, ChipWithCursiveDebugger, ChipWithCursiveDisplay, LoadProgramError, LoadStateError,
 };
 
-/// Error type for errors that occur during parsing the command line arguments
-/// and loading the program based on the arguments.
+/// The maximum number of snapshots kept for rewinding. As a snapshot is taken at
+/// the end of every frame, this allows to go back 30 seconds in time.
+const REWIND_CAPACITY: usize = 1800;
+
+/// The number of cycles between two updates of the debugger view while the
+/// execution is running.
+const DEBUGGER_UPDATE_INTERVAL: u32 = 100;
+
+/// The help printed for `--help`.
+const USAGE: &str = "\
+Usage: emulator_text_ui <path-to-program> [options]
+
+Runs a CHIP-8 program in the terminal. Programs with the .xo8 extension run on
+an XO-CHIP, all other programs on a CHIP-8.
+
+Options:
+  --quirks <preset>          Quirks of the interpreter the program was written
+                             for: cosmac-vip, chip48, super-chip, xo-chip or
+                             modern (default: xo-chip for XO-CHIP programs,
+                             modern otherwise)
+  --ipf <n>                  Instructions executed per frame, i.e., the CPU
+                             speed at 60 frames per second (default: 16)
+  --theme <name>             Colours of the display: default, inverted, green
+                             or amber
+  --keymap <layout-or-path>  Built-in layout (qwerty, azerty, dvorak, numpad)
+                             or path of a keymap configuration
+  --key-timeout <ms>         Time after which keys are released if the terminal
+                             does not report key releases (default: 250)
+  --load-state <slot>        Start from the save state in slot 1 to 4
+  --paused                   Start with the execution paused
+  --seed <n>                 Seed of the random number generator
+  --record <path>            Record the input into a movie
+  --replay <path>            Replay the input from a movie
+  --debug                    Show the debugger view and start paused
+  --break <breakpoint>       Stop at a breakpoint (e.g., 0x2A4, DXYN); may be
+                             repeated
+  --watch <watchpoint>       Stop at a watchpoint (e.g., VA, write:0x300); may
+                             be repeated
+  -h, --help                 Print this help
+";
+
+/// The number of save state slots, which are numbered starting at 1.
+const STATE_SLOTS: u8 = 4;
+
+/// Error type for errors that occur during parsing the command line arguments,
+/// loading the program based on the arguments, and running it.
 enum Error {
-    InvalidUsage(String),
+    MissingProgram,
+    UnexpectedArgument(String),
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue(String, String),
+    ConflictingOptions(&'static str, &'static str),
     CouldNotOpenFile(String),
     CouldNotReadMetadata(String),
     CouldNotReadFile(String),
     InvalidProgram(LoadProgramError),
+    InvalidDebugOption(ParseError),
+    InvalidMovie(LoadMovieError),
+    InvalidMovieState(LoadStateError),
+    InvalidKeymap(KeymapError),
+    InvalidSaveState(String, LoadStateError),
+    CouldNotWriteFile(String),
+}
+
+impl Error {
+    /// Returns whether the error is caused by malformed command line arguments.
+    fn is_usage_error(&self) -> bool {
+        matches!(
+            self,
+            Error::MissingProgram
+                | Error::UnexpectedArgument(_)
+                | Error::UnknownOption(_)
+                | Error::MissingValue(_)
+                | Error::InvalidValue(_, _)
+                | Error::ConflictingOptions(_, _)
+                | Error::InvalidDebugOption(_)
+        )
+    }
 }
 
 /// Represents an event to be processed by the event loop. It is generic
 /// over the type representing the pressed key.
 enum Event<T> {
-    /// Occurs when the key passed in the enum value was pressed.
-    Key(T),
+    /// Occurs when the key passed in the enum value was pressed, including
+    /// repeated presses while the key is held.
+    KeyDown(T),
 
-    /// Indicates that all keys are released. Note that this is a
-    /// hack because OS X currently requires extra permissions to
-    /// listen to key down/up events. To get around this we simply
-    /// read the stdin (indirectly via registering for cursive
-    /// events) and assign one key to trigger releasing all keys.
-    KeyRelease,
+    /// Occurs when the terminal reports that the key passed in the enum value was
+    /// released. Terminals that do not report releases never send this event, in
+    /// which case keys are released automatically (see `KeyTracker`).
+    KeyUp(T),
 
-    /// Decreases the sleep time after each cycle.
+    /// Increases the number of instructions executed per frame.
     SpeedUp,
 
-    /// Increases the sleep time after each cycle.
+    /// Decreases the number of instructions executed per frame.
     SlowDown,
 
+    /// Enables turbo mode if it is disabled, and disables it otherwise. In turbo
+    /// mode, frames are executed as fast as possible.
+    ToggleTurbo,
+
+    /// Saves the state of the chip to the slot passed in the enum value.
+    SaveState(u8),
+
+    /// Restores the state of the chip from the slot passed in the enum value.
+    LoadState(u8),
+
+    /// Restores the most recent snapshot taken for rewinding. Holding the key
+    /// repeatedly sends this event and hence steps backwards in time.
+    Rewind,
+
+    /// Pauses the execution if it is running, and continues it otherwise.
+    TogglePause,
+
+    /// Pauses the execution and executes a single cycle.
+    Step,
+
+    /// Executes a single cycle, or, if the cycle calls a subroutine, continues
+    /// the execution until the subroutine returns. Pauses the execution afterwards.
+    StepOver,
+
     /// Shut down.
     Quit,
 }
 
+/// Represents how the event loop executes the chip.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    /// A cycle is executed in every iteration of the event loop.
+    Running,
+
+    /// No cycles are executed, except for single steps.
+    Paused,
+
+    /// Cycles are executed until the call depth of the chip is at most the
+    /// call depth passed in the enum value. The execution is paused afterwards.
+    SteppingOver(usize),
+}
+
+/// Represents whether the event loop records or replays the input.
+enum MovieMode {
+    /// The input is neither recorded nor replayed.
+    Off,
+
+    /// The input is recorded into the movie passed in the enum value.
+    Recording(Movie),
+
+    /// The input is replayed from the movie passed in the enum value. Input from
+    /// the user is ignored until the replay ends.
+    Replaying(Movie),
+}
+
+/// The options given in the command line arguments.
+#[derive(Clone)]
+struct Options {
+    /// Whether only the help is printed.
+    help: bool,
+
+    program_path: String,
+
+    /// The quirks of the chip, which default to the quirks of its variant.
+    quirks: Option<Quirks>,
+
+    instructions_per_frame: u32,
+
+    /// The palette the display is drawn with.
+    palette: Palette,
+
+    /// The name of a built-in layout or the path of a keymap configuration.
+    keymap: Option<String>,
+
+    /// The time after which keys are released if the terminal does not report
+    /// key releases.
+    auto_release_timeout: Duration,
+
+    /// The save state slot the execution starts from.
+    state_slot: Option<u8>,
+
+    /// Whether execution starts paused.
+    paused: bool,
+
+    seed: Option<u64>,
+
+    /// Whether the debugger view is shown.
+    debug: bool,
+
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+
+    /// The path of the movie to record.
+    record_path: Option<String>,
+
+    /// The path of the movie to replay.
+    replay_path: Option<String>,
+}
+
 /// Represents the channels available to the event loop. It is generic
 /// over the type representing the pressed keys.
 #[derive(Clone)]
@@ -55,57 +243,500 @@ struct EventLoopChannels<T> {
 }
 
 /// The event loop. Constantly loops over (1) process event if there
-/// is any. (2) Invoke cycle on the chip. (3) Update the UI. (4) Sleep
-/// for the cycle sleep time (initially 1ms). (5) Start over.
-fn event_loop<T, P, M>(mut chip: T, io_channels: EventLoopChannels<P>)
+/// is any. (2) Invoke cycle on the chip via the scheduler. (3) If the frame
+/// ended, update the UI and wait for the next frame. (4) Start over. If a cycle
+/// fails or the program exits, this is shown in the UI and the chip is
+/// halted until the user quits. Save states are stored next to the program
+/// at `options.program_path`. At the end of every frame, a snapshot is taken
+/// that can be restored for rewinding. If `options.debug` is set, execution
+/// starts paused and the debugger view is updated regularly. Whenever the chip
+/// reports that the execution stopped (e.g., at a breakpoint), the execution is
+/// paused. Depending on `movie_mode`, the input is recorded or replayed, where
+/// loading a state or rewinding restarts the recording. Returns the finished
+/// recording, if any.
+fn event_loop<T, P, M>(
+    mut chip: T,
+    options: &Options,
+    mut movie_mode: MovieMode,
+    io_channels: EventLoopChannels<P>,
+) -> Option<Movie>
 where
-    T: Chip<PinAddress = P, MemoryAddress = M> + ChipWithCursiveDisplay,
+    T: Chip<PinAddress = P, MemoryAddress = M> + ChipWithCursiveDisplay + ChipWithCursiveDebugger,
+    T::StopReason: std::fmt::Display,
+    P: Copy + PartialEq + Into<u8> + From<u8>,
 {
-    let mut cycle_sleep = 1;
+    let program_path = &options.program_path;
+    let debug = options.debug;
+    let mut scheduler = Scheduler::new(options.instructions_per_frame);
+    let mut key_tracker = KeyTracker::new(options.auto_release_timeout);
+    let mut rewind_buffer = RewindBuffer::new(REWIND_CAPACITY);
+    let mut cycles_since_debugger_update = 0;
+    // the number of cycles executed since the start of the movie
+    let mut cycles = 0;
+    let mut replay_position = 0;
+    let mut mode = if debug || options.paused {
+        RunMode::Paused
+    } else {
+        RunMode::Running
+    };
     loop {
-        match io_channels.key_receiver.try_recv() {
-            Ok(Event::Key(key)) => {
-                chip.set_input_pin(key, true);
+        // while paused, block until the next event instead of spinning
+        let event = match mode {
+            RunMode::Paused => match io_channels.key_receiver.recv() {
+                Ok(event) => Some(event),
+                Err(_) => break,
+            },
+            _ => io_channels.key_receiver.try_recv().ok(),
+        };
+
+        let replaying = matches!(movie_mode, MovieMode::Replaying(_));
+        let mut step = false;
+        match event {
+            Some(Event::KeyDown(_))
+            | Some(Event::KeyUp(_))
+            | Some(Event::LoadState(_))
+            | Some(Event::Rewind)
+                if replaying =>
+            { /* the input is replayed from the movie */ }
+            Some(Event::KeyDown(key)) => {
+                // repeated presses of a held key only postpone its release
+                let newly_pressed = key_tracker.press(key, Instant::now());
+                if newly_pressed {
+                    set_key(&mut chip, &mut movie_mode, cycles, key, true);
+                }
             }
-            Ok(Event::KeyRelease) => {
-                chip.reset_input_pins();
+            Some(Event::KeyUp(key)) => {
+                let was_held = key_tracker.release(key);
+                if was_held {
+                    set_key(&mut chip, &mut movie_mode, cycles, key, false);
+                }
+            }
+            Some(Event::Quit) => {
+                break;
+            }
+            Some(Event::SpeedUp) => {
+                scheduler.set_instructions_per_frame(scheduler.instructions_per_frame() + 1);
+            }
+            Some(Event::SlowDown) => {
+                let instructions_per_frame = scheduler.instructions_per_frame();
+                scheduler.set_instructions_per_frame((instructions_per_frame - 1).max(1));
+            }
+            Some(Event::ToggleTurbo) => {
+                scheduler.set_turbo(!scheduler.turbo());
+            }
+            Some(Event::SaveState(slot)) => {
+                let path = state_slot_path(program_path, slot);
+                if fs::write(&path, chip.save_state()).is_err() {
+                    notify(
+                        "Saving state failed",
+                        format!("Could not write file: {:?}", path),
+                        &io_channels,
+                    );
+                }
             }
-            Ok(Event::Quit) => {
-                return;
+            Some(Event::LoadState(slot)) => {
+                let path = state_slot_path(program_path, slot);
+                let result = fs::read(&path)
+                    .map_err(|_| format!("Could not read file: {:?}", path))
+                    .and_then(|state| chip.load_state(&state).map_err(|e| e.to_string()));
+                match result {
+                    Ok(()) => restart_recording(&mut movie_mode, &chip, &mut cycles),
+                    Err(message) => notify("Loading state failed", message, &io_channels),
+                }
             }
-            Ok(Event::SpeedUp) => {
-                if cycle_sleep > 5 {
-                    cycle_sleep -= 5;
+            Some(Event::Rewind) => {
+                if let Some(snapshot) = rewind_buffer.pop() {
+                    match chip.load_state(&snapshot) {
+                        Ok(()) => restart_recording(&mut movie_mode, &chip, &mut cycles),
+                        Err(e) => notify("Rewinding failed", e.to_string(), &io_channels),
+                    }
                 }
             }
-            Ok(Event::SlowDown) => {
-                cycle_sleep += 5;
+            Some(Event::TogglePause) => {
+                mode = match mode {
+                    RunMode::Paused => RunMode::Running,
+                    _ => RunMode::Paused,
+                };
+            }
+            Some(Event::Step) => {
+                mode = RunMode::Paused;
+                step = true;
             }
-            Err(_) => { /* do nothing */ }
+            Some(Event::StepOver) => {
+                mode = RunMode::SteppingOver(chip.call_depth());
+            }
+            None => { /* do nothing */ }
         };
 
-        chip.cycle();
-        chip.update_ui(&io_channels.gfx_sender);
+        if !replaying {
+            for key in key_tracker.release_expired(Instant::now()) {
+                set_key(&mut chip, &mut movie_mode, cycles, key, false);
+            }
+        }
+
+        if mode == RunMode::Paused && !step {
+            chip.update_ui(&io_channels.gfx_sender);
+            chip.update_debugger_ui(&io_channels.gfx_sender);
+            continue;
+        }
+
+        // while replaying, the timers are ticked by the recorded events instead of
+        // the scheduler, so that they are ticked after the same cycles
+        let mut frame_ended = false;
+        if let MovieMode::Replaying(movie) = &movie_mode {
+            let events = movie.events();
+            while replay_position < events.len() && events[replay_position].0 <= cycles {
+                let event = events[replay_position].1;
+                event.apply(&mut chip);
+                frame_ended |= event == InputEvent::TickTimers;
+                replay_position += 1;
+            }
+            if cycles == movie.length() {
+                let message = if movie.verify(&chip.save_state()) {
+                    "The replay matches the recording.".to_string()
+                } else {
+                    "The replay diverged from the recording.".to_string()
+                };
+                movie_mode = MovieMode::Off;
+                mode = RunMode::Paused;
+                notify("Replay finished", message, &io_channels);
+                continue;
+            }
+        }
+
+        let result = if replaying {
+            chip.cycle().map(|_| false)
+        } else {
+            scheduler.cycle(&mut chip)
+        };
+        match result {
+            Ok(ticked) => {
+                cycles += 1;
+                if ticked {
+                    if let MovieMode::Recording(movie) = &mut movie_mode {
+                        movie.record(cycles, InputEvent::TickTimers);
+                    }
+                    frame_ended = true;
+                }
+            }
+            Err(e) => {
+                chip.update_ui(&io_channels.gfx_sender);
+                chip.update_debugger_ui(&io_channels.gfx_sender);
+                halt("Execution halted", e.to_string(), &io_channels);
+                break;
+            }
+        }
+
+        // stepping over ends once all subroutines called in the meantime returned
+        if let RunMode::SteppingOver(depth) = mode {
+            if chip.call_depth() <= depth {
+                mode = RunMode::Paused;
+            }
+        }
+
+        if let Some(reason) = chip.take_stop_reason() {
+            mode = RunMode::Paused;
+            notify("Execution stopped", reason.to_string(), &io_channels);
+        }
+
+        cycles_since_debugger_update += 1;
+        if mode == RunMode::Paused
+            || (debug && cycles_since_debugger_update >= DEBUGGER_UPDATE_INTERVAL)
+        {
+            chip.update_debugger_ui(&io_channels.gfx_sender);
+            cycles_since_debugger_update = 0;
+        }
+
+        if chip.has_exited() {
+            halt(
+                "Program exited",
+                "The program exited.".to_string(),
+                &io_channels,
+            );
+            break;
+        }
+
+        if frame_ended {
+            rewind_buffer.push(chip.save_state());
+            chip.update_ui(&io_channels.gfx_sender);
+            scheduler.wait_for_frame();
+        }
+    }
+
+    match movie_mode {
+        MovieMode::Recording(mut movie) => {
+            movie.finish(cycles, &chip.save_state());
+            Some(movie)
+        }
+        _ => None,
+    }
+}
+
+/// Sets the input pin of `chip` corresponding to `key` to `value`, and records the
+/// change if a movie is recorded.
+fn set_key<T, P>(chip: &mut T, movie_mode: &mut MovieMode, cycles: u64, key: P, value: bool)
+where
+    T: Chip<PinAddress = P>,
+    P: Copy + Into<u8>,
+{
+    if let MovieMode::Recording(movie) = movie_mode {
+        movie.record(cycles, InputEvent::SetInputPin(key.into(), value));
+    }
+    chip.set_input_pin(key, value);
+}
+
+/// Restarts the recording, if any, from the current state of `chip`. This keeps
+/// the movie consistent after the state was replaced by loading a state.
+fn restart_recording<T: Chip>(movie_mode: &mut MovieMode, chip: &T, cycles: &mut u64) {
+    if let MovieMode::Recording(movie) = movie_mode {
+        *movie = Movie::new(chip.save_state());
+        *cycles = 0;
+    }
+}
+
+/// Returns the path of the file storing the given save state `slot` of the
+/// program at `program_path`.
+fn state_slot_path(program_path: &str, slot: u8) -> String {
+    format!("{}.state{}", program_path, slot)
+}
+
+/// Shows a dialog with the given `title` and `message` in the UI, which the user
+/// can dismiss. Execution continues while the dialog is shown.
+fn notify<P>(title: &'static str, message: String, io_channels: &EventLoopChannels<P>) {
+    io_channels
+        .gfx_sender
+        .send(Box::new(move |s: &mut cursive::Cursive| {
+            s.add_layer(Dialog::text(message).title(title).dismiss_button("OK"));
+        }))
+        .expect("Sending notification dialog failed");
+}
+
+/// Shows a dialog with the given `title` and `message` in the UI and blocks until
+/// the user quits.
+fn halt<P>(title: &'static str, message: String, io_channels: &EventLoopChannels<P>) {
+    io_channels
+        .gfx_sender
+        .send(Box::new(move |s: &mut cursive::Cursive| {
+            s.add_layer(Dialog::text(format!("{}\n\nPress Esc to quit.", message)).title(title));
+        }))
+        .expect("Sending halt dialog failed");
+
+    loop {
+        match io_channels.key_receiver.recv() {
+            Ok(Event::Quit) | Err(_) => return,
+            Ok(_) => { /* ignore all other events while halted */ }
+        }
+    }
+}
+
+/// Parses the command line arguments `args`, which consist of the path to the
+/// program and the options in any order (see `USAGE`). Breakpoints and
+/// watchpoints may stop a cycle before the instruction is executed, hence they
+/// cannot be combined with recording or replaying a movie. Without the UI, there
+/// is no input to record and the execution cannot be paused.
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, Error> {
+    let mut options = Options {
+        help: false,
+        program_path: String::new(),
+        quirks: None,
+        instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+        palette: theme("default").unwrap(),
+        keymap: None,
+        auto_release_timeout: DEFAULT_AUTO_RELEASE_TIMEOUT,
+        state_slot: None,
+        paused: false,
+        seed: None,
+        debug: false,
+        breakpoints: vec![],
+        watchpoints: vec![],
+        record_path: None,
+        replay_path: None,
+    };
+    let mut program_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                options.help = true;
+                return Ok(options);
+            }
+            "--quirks" => {
+                let preset = next_value(&mut args, &arg)?;
+                options.quirks =
+                    Some(Quirks::preset(&preset).ok_or(Error::InvalidValue(arg, preset))?);
+            }
+            "--ipf" => {
+                let value = next_value(&mut args, &arg)?;
+                options.instructions_per_frame = match value.parse() {
+                    Ok(instructions_per_frame) if instructions_per_frame > 0 => {
+                        instructions_per_frame
+                    }
+                    _ => return Err(Error::InvalidValue(arg, value)),
+                };
+            }
+            "--theme" => {
+                let name = next_value(&mut args, &arg)?;
+                options.palette = theme(&name).ok_or(Error::InvalidValue(arg, name))?;
+            }
+            "--keymap" => {
+                options.keymap = Some(next_value(&mut args, &arg)?);
+            }
+            "--key-timeout" => {
+                let millis = parse_value(&mut args, &arg)?;
+                options.auto_release_timeout = Duration::from_millis(millis);
+            }
+            "--load-state" => {
+                let value = next_value(&mut args, &arg)?;
+                options.state_slot = match value.parse() {
+                    Ok(slot) if (1..=STATE_SLOTS).contains(&slot) => Some(slot),
+                    _ => return Err(Error::InvalidValue(arg, value)),
+                };
+            }
+            "--paused" => {
+                options.paused = true;
+            }
+            "--seed" => {
+                options.seed = Some(parse_value(&mut args, &arg)?);
+            }
+            "--debug" => {
+                options.debug = true;
+            }
+            "--break" => {
+                let breakpoint = next_value(&mut args, &arg)?;
+                options
+                    .breakpoints
+                    .push(breakpoint.parse().map_err(Error::InvalidDebugOption)?);
+            }
+            "--watch" => {
+                let watchpoint = next_value(&mut args, &arg)?;
+                options
+                    .watchpoints
+                    .push(watchpoint.parse().map_err(Error::InvalidDebugOption)?);
+            }
+            "--record" => {
+                options.record_path = Some(next_value(&mut args, &arg)?);
+            }
+            "--replay" => {
+                options.replay_path = Some(next_value(&mut args, &arg)?);
+            }
+            _ if arg.starts_with('-') => return Err(Error::UnknownOption(arg)),
+            _ if program_path.is_none() => program_path = Some(arg),
+            _ => return Err(Error::UnexpectedArgument(arg)),
+        }
+    }
+    options.program_path = program_path.ok_or(Error::MissingProgram)?;
+
+    let record = options.record_path.is_some();
+    let replay = options.replay_path.is_some();
+    let points = !options.breakpoints.is_empty() || !options.watchpoints.is_empty();
+    let conflicts = [
+        (record && replay, "--record", "--replay"),
+        (record && points, "--record", "--break/--watch"),
+        (replay && points, "--replay", "--break/--watch"),
+        (
+            replay && options.state_slot.is_some(),
+            "--replay",
+            "--load-state",
+        ),
+    ];
+    for (conflict, first, second) in conflicts.iter() {
+        if *conflict {
+            return Err(Error::ConflictingOptions(first, second));
+        }
+    }
+    Ok(options)
+}
+
+/// Returns the next argument in `args`, which is the value of `option`.
+fn next_value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String, Error> {
+    args.next()
+        .ok_or_else(|| Error::MissingValue(option.to_string()))
+}
+
+/// Parses the next argument in `args`, which is the value of `option`.
+fn parse_value<I, T>(args: &mut I, option: &str) -> Result<T, Error>
+where
+    I: Iterator<Item = String>,
+    T: FromStr,
+{
+    let value = next_value(args, option)?;
+    value
+        .parse()
+        .map_err(|_| Error::InvalidValue(option.to_string(), value))
+}
+
+/// Loads the program based on `options`. Programs with the `.xo8` extension are
+/// loaded into an XO-CHIP, all other programs are loaded into a CHIP-8. If a save
+/// state slot is given, the state is restored from the slot afterwards.
+fn load_program(options: &Options) -> Result<Chip8, Error> {
+    let path = &options.program_path;
+    let variant = if path.ends_with(".xo8") {
+        Variant::XoChip
+    } else {
+        Variant::Chip8
+    };
+    let quirks = options.quirks.unwrap_or_else(|| match variant {
+        Variant::XoChip => Quirks::xo_chip(),
+        _ => Quirks::default(),
+    });
+    let mut chip8 = match options.seed {
+        Some(seed) => Chip8::with_seed(variant, quirks, seed),
+        None => Chip8::with_variant(variant, quirks),
+    };
+    let program_bytes = load_program_helper(path)?;
+    chip8
+        .load_program(&program_bytes)
+        .map_err(Error::InvalidProgram)?;
+    for breakpoint in &options.breakpoints {
+        chip8.add_breakpoint(*breakpoint);
+    }
+    for watchpoint in &options.watchpoints {
+        chip8.add_watchpoint(*watchpoint);
+    }
+
+    if let Some(slot) = options.state_slot {
+        let state_path = state_slot_path(path, slot);
+        let state =
+            fs::read(&state_path).map_err(|_| Error::CouldNotReadFile(state_path.clone()))?;
+        chip8
+            .load_state(&state)
+            .map_err(|e| Error::InvalidSaveState(state_path, e))?;
+    }
+    Ok(chip8)
+}
 
-        std::thread::sleep(Duration::from_millis(cycle_sleep));
+/// Determines the keymap for the program based on `options`. The keymap option
+/// is either the name of a built-in layout, or the path of a keymap
+/// configuration, which may override the default keymap for the program.
+/// Without the option, the QWERTY layout is used.
+fn keymap_from_options(options: &Options) -> Result<Keymap, Error> {
+    let source = match &options.keymap {
+        Some(source) => source,
+        None => return Ok(Keymap::default()),
+    };
+    if let Some(keymap) = Keymap::layout(source) {
+        return Ok(keymap);
     }
+    let config = fs::read_to_string(source).map_err(|_| Error::CouldNotReadFile(source.clone()))?;
+    let config = KeymapConfig::parse(&config).map_err(Error::InvalidKeymap)?;
+    Ok(config.keymap_for(&options.program_path))
 }
 
-/// Loads a program based on the given arguments. If there are no arguments, it
-/// loads a simple default program, whereas it interprets the first argument as
-/// path to the program to load and attempts to load the program from there.
-fn load_program_from_args(chip8: &mut Chip8) -> Result<(), Error> {
-    let args: Vec<String> = env::args().collect();
-    match args.len() {
-        1 => Err(Error::InvalidUsage(
-            "Expecting path to the program to load as command line argument.".to_string(),
-        )),
-        _ => {
-            let program_bytes = load_program_helper(&args[1])?;
+/// Determines the movie mode based on `options`. A movie to replay is loaded and
+/// its initial state is restored in `chip8`.
+fn movie_mode_from_options(chip8: &mut Chip8, options: &Options) -> Result<MovieMode, Error> {
+    if options.record_path.is_some() {
+        return Ok(MovieMode::Recording(Movie::new(chip8.save_state())));
+    }
+    match &options.replay_path {
+        Some(path) => {
+            let bytes = fs::read(path).map_err(|_| Error::CouldNotReadFile(path.clone()))?;
+            let movie = Movie::from_bytes(&bytes).map_err(Error::InvalidMovie)?;
             chip8
-                .load_program(&program_bytes)
-                .map_err(Error::InvalidProgram)
+                .load_state(movie.initial_state())
+                .map_err(Error::InvalidMovieState)?;
+            Ok(MovieMode::Replaying(movie))
         }
+        None => Ok(MovieMode::Off),
     }
 }
 
@@ -119,94 +750,227 @@ fn load_program_helper(path: &str) -> Result<Vec<u8>, Error> {
     Ok(buffer)
 }
 
-/// Constructs the UI and spawns the event loop and the UI thread.
-fn main() {
-    let mut chip8 = Chip8::new();
+/// Wraps `view` such that key events of keys in `keymap` are sent to the event
+/// loop as key presses and releases of the keypad. Presses arrive as characters,
+/// and releases as key events of the kitty keyboard protocol if the terminal
+/// supports it.
+fn with_keypad<V: View>(view: V, keymap: Keymap, sender: Sender<Event<u8>>) -> OnEventView<V> {
+    OnEventView::new(view).on_pre_event_inner(EventTrigger::any(), move |_, event| {
+        let (key, kind) = match event {
+            UiEvent::Char(key) => (*key, KeyEventKind::Press),
+            UiEvent::Unknown(bytes) => parse_kitty_key_event(bytes)?,
+            _ => return None,
+        };
+        let pin = keymap.pin(key)?;
+        let event = match kind {
+            KeyEventKind::Release => Event::KeyUp(pin),
+            _ => Event::KeyDown(pin),
+        };
+        sender.send(event).unwrap();
+        Some(EventResult::Consumed(None))
+    })
+}
+
+/// Restores the keyboard protocol of the terminal when dropped, so that it is
+/// restored however the UI stops running.
+struct KittyKeyboardGuard;
 
-    if let Err(e) = load_program_from_args(&mut chip8) {
-        println!("{}", e);
-        return;
+impl Drop for KittyKeyboardGuard {
+    fn drop(&mut self) {
+        print!("{}", KITTY_KEYBOARD_DISABLE);
+        let _ = std::io::stdout().flush();
     }
+}
+
+/// Plays the sound of `chip8` on the default audio device. Returns the chip along
+/// with the output, which has to be kept open while the program runs. If there is
+/// no suitable device, the program runs without sound.
+#[cfg(feature = "audio")]
+fn enable_audio(mut chip8: Chip8) -> (Chip8, Option<CpalOutput>) {
+    match CpalOutput::open() {
+        Ok((output, sink)) => {
+            chip8.set_audio_sink(Box::new(sink));
+            (chip8, Some(output))
+        }
+        Err(e) => {
+            eprintln!("Running without sound: {}", e);
+            (chip8, None)
+        }
+    }
+}
+
+/// Runs the program based on `options` by constructing the UI and spawning the
+/// event loop and the UI thread. A recorded movie is written once the event loop
+/// finished.
+fn run(options: Options) -> Result<(), Error> {
+    let mut chip8 = load_program(&options)?;
+    let keymap = keymap_from_options(&options)?;
+    let movie_mode = movie_mode_from_options(&mut chip8, &options)?;
+    #[cfg(feature = "audio")]
+    let (chip8, _audio_output) = enable_audio(chip8);
 
     let mut siv = cursive::default();
 
     let cb_sink = siv.cb_sink().clone();
     let (key_sender, key_receiver) = bounded::<Event<u8>>(10);
 
+    let event_loop_options = options.clone();
     let event_loop_handle = std::thread::spawn(move || {
         event_loop(
             chip8,
+            &event_loop_options,
+            movie_mode,
             EventLoopChannels {
                 gfx_sender: cb_sink,
                 key_receiver: key_receiver,
             },
-        );
+        )
     });
 
     let sender = key_sender.clone();
-    siv.add_global_callback(cursive::event::Key::Esc, move |s| {
+    siv.add_global_callback(Key::Esc, move |s| {
         sender.send(Event::Quit).unwrap();
         s.quit();
     });
 
-    for (i, j) in &[
-        ('1', 0x1),
-        ('2', 0x2),
-        ('3', 0x3),
-        ('4', 0xC),
-        ('q', 0x4),
-        ('w', 0x5),
-        ('e', 0x6),
-        ('r', 0xD),
-        ('a', 0x7),
-        ('s', 0x8),
-        ('d', 0x9),
-        ('f', 0xE),
-        ('z', 0xA),
-        ('x', 0x0),
-        ('c', 0xB),
-        ('v', 0xF),
+    let sender = key_sender.clone();
+    siv.add_global_callback(Key::Up, move |_s| {
+        sender.send(Event::SpeedUp).unwrap();
+    });
+
+    let sender = key_sender.clone();
+    siv.add_global_callback(Key::Down, move |_s| {
+        sender.send(Event::SlowDown).unwrap();
+    });
+
+    let sender = key_sender.clone();
+    siv.add_global_callback(Key::Tab, move |_s| {
+        sender.send(Event::ToggleTurbo).unwrap();
+    });
+
+    let sender = key_sender.clone();
+    siv.add_global_callback(Key::Backspace, move |_s| {
+        sender.send(Event::Rewind).unwrap();
+    });
+
+    // (save key, load key, slot)
+    for (save, load, slot) in &[
+        (Key::F1, Key::F5, 1),
+        (Key::F2, Key::F6, 2),
+        (Key::F3, Key::F7, 3),
+        (Key::F4, Key::F8, 4),
     ] {
         let sender = key_sender.clone();
-        siv.add_global_callback(*i, move |_s| {
-            sender.send(Event::Key(*j as u8)).unwrap();
+        siv.add_global_callback(*save, move |_s| {
+            sender.send(Event::SaveState(*slot)).unwrap();
+        });
+        let sender = key_sender.clone();
+        siv.add_global_callback(*load, move |_s| {
+            sender.send(Event::LoadState(*slot)).unwrap();
         });
     }
 
     let sender = key_sender.clone();
-    siv.add_global_callback(' ', move |_s| {
-        sender.send(Event::KeyRelease).unwrap();
+    siv.add_global_callback(Key::F9, move |_s| {
+        sender.send(Event::TogglePause).unwrap();
     });
 
     let sender = key_sender.clone();
-    siv.add_global_callback(cursive::event::Key::Up, move |_s| {
-        sender.send(Event::SpeedUp).unwrap();
+    siv.add_global_callback(Key::F10, move |_s| {
+        sender.send(Event::StepOver).unwrap();
     });
 
     let sender = key_sender.clone();
-    siv.add_global_callback(cursive::event::Key::Down, move |_s| {
-        sender.send(Event::SlowDown).unwrap();
+    siv.add_global_callback(Key::F11, move |_s| {
+        sender.send(Event::Step).unwrap();
     });
 
-    siv.add_layer(Display::default());
+    if options.debug {
+        siv.add_layer(with_keypad(debugger_layout(), keymap, key_sender));
+    } else {
+        siv.add_layer(with_keypad(
+            Display::default()
+                .with_palette(options.palette)
+                .with_name(DISPLAY_NAME),
+            keymap,
+            key_sender,
+        ));
+    }
+
+    // key releases are only reported once the UI took over the terminal
+    siv.cb_sink()
+        .send(Box::new(|_| {
+            print!("{}", KITTY_KEYBOARD_ENABLE);
+            let _ = std::io::stdout().flush();
+        }))
+        .expect("Sending keyboard protocol request failed");
 
+    let keyboard_guard = KittyKeyboardGuard;
     siv.run();
+    drop(keyboard_guard);
 
-    event_loop_handle
+    let recording = event_loop_handle
         .join()
         .expect("Could not join event loop handle");
+    if let (Some(movie), Some(path)) = (recording, &options.record_path) {
+        fs::write(path, movie.to_bytes()).map_err(|_| Error::CouldNotWriteFile(path.clone()))?;
+    }
+    Ok(())
+}
+
+/// Parses the command line arguments and runs the program. Errors are printed to
+/// stderr, along with a hint to the help for errors in the arguments, and the
+/// process exits with a non-zero status.
+fn main() {
+    let result = parse_args(env::args().skip(1)).and_then(|options| {
+        if options.help {
+            print!("{}", USAGE);
+            Ok(())
+        } else {
+            run(options)
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        if e.is_usage_error() {
+            eprintln!("Run `emulator_text_ui --help` for usage.");
+            process::exit(2);
+        }
+        process::exit(1);
+    }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Error::InvalidUsage(message) => write!(f, "Usage: {}", message),
+            Error::MissingProgram => write!(f, "Expecting path to the program to load."),
+            Error::UnexpectedArgument(arg) => write!(f, "Unexpected argument {:?}.", arg),
+            Error::UnknownOption(option) => write!(f, "Unknown option {:?}.", option),
+            Error::MissingValue(option) => write!(f, "Option {} expects a value.", option),
+            Error::InvalidValue(option, value) => {
+                write!(f, "Invalid value {:?} for option {}.", value, option)?;
+                let choices = match option.as_str() {
+                    "--quirks" => Quirks::preset_names(),
+                    "--theme" => theme_names(),
+                    _ => return Ok(()),
+                };
+                write!(f, " Available values are {}.", choices.join(", "))
+            }
+            Error::ConflictingOptions(first, second) => {
+                write!(f, "Options {} and {} cannot be combined.", first, second)
+            }
             Error::CouldNotOpenFile(message) => write!(f, "Could not open file: {:?}", message),
             Error::CouldNotReadMetadata(message) => {
                 write!(f, "Could not read metadata: {:?}", message)
             }
             Error::CouldNotReadFile(message) => write!(f, "Could not read file: {:?}", message),
             Error::InvalidProgram(error) => write!(f, "{}", error),
+            Error::InvalidDebugOption(error) => write!(f, "{}", error),
+            Error::InvalidMovie(error) => write!(f, "{}", error),
+            Error::InvalidMovieState(error) => write!(f, "{}", error),
+            Error::InvalidKeymap(error) => write!(f, "{}", error),
+            Error::InvalidSaveState(path, error) => write!(f, "{:?}: {}", path, error),
+            Error::CouldNotWriteFile(path) => write!(f, "Could not write file: {:?}", path),
         }
     }
 }
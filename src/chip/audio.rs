@@ -0,0 +1,273 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::chip::scheduler::FRAME_RATE;
+
+/// The sample rate used by sinks that can choose it freely, in Hz.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// The length of a tone's pattern in bytes. Each bit of the pattern is a sample.
+pub const TONE_PATTERN_LEN: usize = 16;
+
+/// The amplitude of the synthesized square wave.
+const VOLUME: i16 = i16::MAX / 4;
+
+/// The sound played by a chip, a 1-bit waveform that is repeated while the sound
+/// is played.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    /// The waveform, where the most significant bit of the first byte is the
+    /// first sample. A set bit represents a high sample.
+    pub pattern: [u8; TONE_PATTERN_LEN],
+
+    /// The number of samples of the pattern played per second.
+    pub rate: f64,
+}
+
+/// Represents a device playing or storing the sound of a chip. The chip drives
+/// the sink once per frame, i.e., whenever its timers are ticked.
+pub trait AudioSink: Send {
+    /// Plays a frame, which lasts `1 / FRAME_RATE` seconds. `tone` is the sound
+    /// played during the frame, or `None` if the chip is silent.
+    fn play_frame(&mut self, tone: Option<&Tone>);
+
+    /// Flushes the played frames to the underlying device. Returns the first
+    /// error that occurred since the sink was created, if any.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A software synthesizer turning tones into 16 bit samples of a square wave. The
+/// phase of the wave is kept across frames, so that consecutive frames playing
+/// the same tone do not click.
+pub struct SquareWave {
+    sample_rate: u32,
+
+    /// The current position in the pattern, measured in samples of the pattern.
+    phase: f64,
+}
+
+impl SquareWave {
+    /// Constructs a synthesizer producing `sample_rate` samples per second.
+    pub fn new(sample_rate: u32) -> Self {
+        SquareWave {
+            sample_rate,
+            phase: 0.0,
+        }
+    }
+
+    /// Returns the number of samples produced per second.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Appends the samples of a single frame playing `tone` to `samples`. Silent
+    /// frames consist of zero samples.
+    pub fn synthesize(&mut self, tone: Option<&Tone>, samples: &mut Vec<i16>) {
+        let len = (self.sample_rate / FRAME_RATE) as usize;
+        let tone = match tone {
+            Some(tone) => tone,
+            None => {
+                self.phase = 0.0;
+                samples.resize(samples.len() + len, 0);
+                return;
+            }
+        };
+
+        let pattern_len = (TONE_PATTERN_LEN * 8) as f64;
+        let step = tone.rate / self.sample_rate as f64;
+        for _ in 0..len {
+            let bit = self.phase as usize;
+            let high = tone.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            samples.push(if high { VOLUME } else { -VOLUME });
+            self.phase = (self.phase + step) % pattern_len;
+        }
+    }
+}
+
+/// A sink writing the sound to a mono 16 bit PCM WAV file. The sizes in the header
+/// are only valid once the sink is finished.
+pub struct WavSink<W: Write + Seek + Send> {
+    writer: W,
+    synthesizer: SquareWave,
+    samples: Vec<i16>,
+
+    /// The number of bytes of samples written so far.
+    data_len: u32,
+
+    /// The first error that occurred during writing, if any.
+    error: Option<io::Error>,
+}
+
+impl<W: Write + Seek + Send> WavSink<W> {
+    /// Constructs a sink writing to `writer` at `sample_rate` samples per second.
+    /// The header is written immediately.
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let mut header = vec![];
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&36_u32.to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16_u32.to_le_bytes());
+        // PCM, mono
+        header.extend_from_slice(&1_u16.to_le_bytes());
+        header.extend_from_slice(&1_u16.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+        // block alignment and bits per sample
+        header.extend_from_slice(&2_u16.to_le_bytes());
+        header.extend_from_slice(&16_u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0_u32.to_le_bytes());
+        writer.write_all(&header)?;
+
+        Ok(WavSink {
+            writer,
+            synthesizer: SquareWave::new(sample_rate),
+            samples: vec![],
+            data_len: 0,
+            error: None,
+        })
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Seek + Send> AudioSink for WavSink<W> {
+    fn play_frame(&mut self, tone: Option<&Tone>) {
+        if self.error.is_some() {
+            return;
+        }
+        self.samples.clear();
+        self.synthesizer.synthesize(tone, &mut self.samples);
+        let bytes: Vec<u8> = self
+            .samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes().to_vec())
+            .collect();
+        match self.writer.write_all(&bytes) {
+            Ok(()) => self.data_len += bytes.len() as u32,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::chip8::{quirks::Quirks, variant::Variant, Chip8};
+    use crate::chip::Chip;
+    use std::sync::{Arc, Mutex};
+
+    /// An audio sink remembering the tones of all frames played.
+    struct RecordingSink(Arc<Mutex<Vec<Option<Tone>>>>);
+
+    impl AudioSink for RecordingSink {
+        fn play_frame(&mut self, tone: Option<&Tone>) {
+            self.0.lock().unwrap().push(tone.copied());
+        }
+    }
+
+    #[test]
+    fn test_audio_sink() {
+        // LD V0, 2; LD ST, V0; LD I, 0x212; AUDIO; LD V0, 112; PITCH V0; LD V0, 1;
+        // LD ST, V0; JP 0x210
+        let mut program = vec![
+            0x60, 0x02, 0xF0, 0x18, 0xA2, 0x12, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A, 0x60, 0x01,
+            0xF0, 0x18, 0x12, 0x10,
+        ];
+        program.extend_from_slice(&[0xAA; TONE_PATTERN_LEN]);
+        let mut state = Chip8::with_variant(Variant::XoChip, Quirks::xo_chip());
+        state
+            .load_program(&program)
+            .expect("Loading program failed");
+        let frames = Arc::new(Mutex::new(vec![]));
+        state.set_audio_sink(Box::new(RecordingSink(Arc::clone(&frames))));
+        for _ in 0..2 {
+            state.cycle().expect("Cycle failed");
+        }
+        for _ in 0..3 {
+            state.tick_timers();
+        }
+
+        // the beep is played at 4000 Hz unless the program loads a pattern
+        let beep = Tone {
+            pattern: [0xF0; TONE_PATTERN_LEN],
+            rate: 4000.0,
+        };
+        assert_eq!(*frames.lock().unwrap(), vec![Some(beep), Some(beep), None]);
+
+        // the sink survives loading a state, and plays the XO-CHIP pattern and pitch
+        for _ in 0..6 {
+            state.cycle().expect("Cycle failed");
+        }
+        let snapshot = state.save_state();
+        state.load_state(&snapshot).expect("Loading state failed");
+        state.tick_timers();
+        assert_eq!(
+            frames.lock().unwrap()[3],
+            Some(Tone {
+                pattern: [0xAA; TONE_PATTERN_LEN],
+                rate: 8000.0,
+            })
+        );
+        assert!(state.take_audio_sink().is_some());
+    }
+
+    #[test]
+    fn test_square_wave() {
+        // one sample of the pattern per sample of the output
+        let mut synthesizer = SquareWave::new(4800);
+        let tone = Tone {
+            pattern: [0xC0; 16],
+            rate: 4800.0,
+        };
+        let mut samples = vec![];
+        synthesizer.synthesize(Some(&tone), &mut samples);
+        assert_eq!(samples.len(), 80);
+        for (i, sample) in samples.iter().enumerate() {
+            assert_eq!(*sample > 0, i % 8 < 2);
+        }
+
+        // silent frames consist of zero samples
+        synthesizer.synthesize(None, &mut samples);
+        assert_eq!(samples.len(), 160);
+        assert!(samples[80..].iter().all(|sample| *sample == 0));
+    }
+
+    #[test]
+    fn test_wav_sink() {
+        let mut sink = WavSink::new(std::io::Cursor::new(vec![]), 6000).expect("Writing failed");
+        let tone = Tone {
+            pattern: [0xF0; 16],
+            rate: 4000.0,
+        };
+        sink.play_frame(Some(&tone));
+        sink.play_frame(None);
+        sink.finish().expect("Writing failed");
+
+        let bytes = sink.into_inner().into_inner();
+        let data_len = 2 * 100 * 2;
+        assert_eq!(bytes.len(), 44 + data_len);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(bytes[4..8], ((36 + data_len) as u32).to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(bytes[24..28], 6000_u32.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(bytes[40..44], (data_len as u32).to_le_bytes());
+    }
+}
@@ -0,0 +1,561 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The maximum depth of nested include files. This prevents infinite recursion
+/// in case of cyclic includes.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// The maximum depth of constants referring to other constants. This prevents
+/// infinite recursion in case of cyclic definitions.
+const MAX_SYMBOL_DEPTH: usize = 16;
+
+/// Represents a location in the assembler source, i.e., a line of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The file containing the line.
+    pub file: String,
+
+    /// The line number, starting at 1.
+    pub line: usize,
+}
+
+/// Error type for errors that occur during assembling a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssemblerError {
+    /// The file with the given path could not be read.
+    CouldNotReadFile(String),
+    /// The given mnemonic is not known.
+    UnknownMnemonic(Location, String),
+    /// The given operands are not valid for the instruction or directive.
+    InvalidOperands(Location, String),
+    /// The given symbol is neither a label nor a constant.
+    UnknownSymbol(Location, String),
+    /// The given symbol is defined more than once.
+    DuplicateSymbol(Location, String),
+    /// The given expression could not be parsed.
+    InvalidExpression(Location, String),
+    /// The given value does not fit into the operand it is used for.
+    ValueOutOfRange(Location, i64),
+    /// Include files are nested too deeply, which is usually caused by cyclic
+    /// includes.
+    IncludeDepthExceeded(Location),
+}
+
+/// Represents a statement of the assembler source that emits bytes.
+enum Statement {
+    /// An instruction, consisting of the mnemonic and its operands.
+    Instruction(String, Vec<String>),
+    /// A `DB` directive, which emits one byte per expression.
+    Bytes(Vec<String>),
+    /// A `DW` directive, which emits one big-endian word per expression.
+    Words(Vec<String>),
+}
+
+/// Represents a symbol defined in the assembler source.
+enum Symbol {
+    /// A label, which resolves to the address it is defined at.
+    Label(u16),
+    /// A constant, which resolves to the value of its expression.
+    Constant(String, Location),
+}
+
+/// Represents an operand of an instruction.
+enum Operand {
+    /// A register `V0` to `VF`.
+    Register(u8),
+    /// The index register `I`.
+    Index,
+    /// The memory at the index register, `[I]`.
+    IndirectIndex,
+    /// The delay timer `DT`.
+    DelayTimer,
+    /// The sound timer `ST`.
+    SoundTimer,
+    /// A key press `K`.
+    Key,
+    /// The font character `F`.
+    Font,
+    /// The big font character `HF`.
+    BigFont,
+    /// The binary coded decimal representation `B`.
+    Bcd,
+    /// The RPL user flags `R`.
+    Rpl,
+    /// A 16 bit address, `LONG <expression>`.
+    Long(String),
+    /// A value given by an expression.
+    Value(String),
+}
+
+/// Holds the state of the assembler while processing the source.
+struct Assembler {
+    /// The address at which the next statement is located.
+    address: u16,
+    /// The statements together with their address and location.
+    statements: Vec<(u16, Location, Statement)>,
+    /// The symbols defined in the source.
+    symbols: HashMap<String, Symbol>,
+}
+
+/// Assembles the given `source` into a program that is loaded at `base_address`.
+/// Include files are resolved relative to the current working directory.
+pub fn assemble(source: &str, base_address: u16) -> Result<Vec<u8>, AssemblerError> {
+    let mut assembler = Assembler::new(base_address);
+    assembler.parse(source, "<input>", Path::new("."), 0)?;
+    assembler.emit()
+}
+
+/// Assembles the source in the file at `path` into a program that is loaded at
+/// `base_address`. Include files are resolved relative to the including file.
+pub fn assemble_file(path: &Path, base_address: u16) -> Result<Vec<u8>, AssemblerError> {
+    let mut assembler = Assembler::new(base_address);
+    assembler.parse_file(path, 0)?;
+    assembler.emit()
+}
+
+impl Assembler {
+    fn new(base_address: u16) -> Self {
+        Assembler {
+            address: base_address,
+            statements: vec![],
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// Reads the file at `path` and parses its content.
+    fn parse_file(&mut self, path: &Path, depth: usize) -> Result<(), AssemblerError> {
+        let source = fs::read_to_string(path)
+            .map_err(|_| AssemblerError::CouldNotReadFile(path.display().to_string()))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new("."));
+        self.parse(&source, &path.display().to_string(), directory, depth)
+    }
+
+    /// Parses the given `source`, which originates from `file`. This records the
+    /// statements and symbols of the source, and processes include directives
+    /// relative to `directory`.
+    fn parse(
+        &mut self,
+        source: &str,
+        file: &str,
+        directory: &Path,
+        depth: usize,
+    ) -> Result<(), AssemblerError> {
+        for (i, line) in source.lines().enumerate() {
+            let location = Location {
+                file: file.to_string(),
+                line: i + 1,
+            };
+
+            let mut line = strip_comment(line).trim();
+            if let Some(colon) = line.find(':') {
+                let label = line[..colon].trim();
+                if is_symbol(label) {
+                    self.define(&location, label, Symbol::Label(self.address))?;
+                    line = line[colon + 1..].trim();
+                }
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let (mnemonic, rest) = split_first_word(line);
+            let (second, value) = split_first_word(rest);
+            if second.eq_ignore_ascii_case("EQU") {
+                if !is_symbol(mnemonic) || value.is_empty() {
+                    return Err(AssemblerError::InvalidOperands(location, line.to_string()));
+                }
+                let constant = Symbol::Constant(value.to_string(), location.clone());
+                self.define(&location, mnemonic, constant)?;
+                continue;
+            }
+
+            let mnemonic = mnemonic.to_ascii_uppercase();
+            let operands = split_operands(rest);
+            let (statement, size) = match mnemonic.as_str() {
+                "INCLUDE" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(AssemblerError::IncludeDepthExceeded(location));
+                    }
+                    let path = rest.trim().trim_matches('"');
+                    if path.is_empty() {
+                        return Err(AssemblerError::InvalidOperands(location, line.to_string()));
+                    }
+                    self.parse_file(&directory.join(PathBuf::from(path)), depth + 1)?;
+                    continue;
+                }
+                "DB" => {
+                    let size = operands.len();
+                    (Statement::Bytes(operands), size)
+                }
+                "DW" => {
+                    let size = 2 * operands.len();
+                    (Statement::Words(operands), size)
+                }
+                _ => {
+                    let is_long = operands
+                        .get(1)
+                        .map(|operand| matches!(parse_operand(operand), Operand::Long(_)))
+                        .unwrap_or(false);
+                    let size = if is_long { 4 } else { 2 };
+                    (Statement::Instruction(mnemonic, operands), size)
+                }
+            };
+            self.statements.push((self.address, location, statement));
+            self.address = self.address.wrapping_add(size as u16);
+        }
+        Ok(())
+    }
+
+    /// Defines the symbol `name`, and returns an error if it is already defined.
+    fn define(
+        &mut self,
+        location: &Location,
+        name: &str,
+        symbol: Symbol,
+    ) -> Result<(), AssemblerError> {
+        let key = name.to_ascii_uppercase();
+        if self.symbols.contains_key(&key) {
+            return Err(AssemblerError::DuplicateSymbol(
+                location.clone(),
+                name.to_string(),
+            ));
+        }
+        self.symbols.insert(key, symbol);
+        Ok(())
+    }
+
+    /// Emits the bytes of all statements, resolving all symbols.
+    fn emit(&self) -> Result<Vec<u8>, AssemblerError> {
+        let mut bytes = vec![];
+        for (_, location, statement) in &self.statements {
+            let evaluate = |expression: &str| self.evaluate(location, expression, 0);
+            match statement {
+                Statement::Instruction(mnemonic, operands) => {
+                    bytes.extend(self.encode(location, mnemonic, operands)?);
+                }
+                Statement::Bytes(expressions) => {
+                    for expression in expressions {
+                        bytes.push(in_range(location, evaluate(expression)?, 0xFF)? as u8);
+                    }
+                }
+                Statement::Words(expressions) => {
+                    for expression in expressions {
+                        let word = in_range(location, evaluate(expression)?, 0xFFFF)?;
+                        bytes.push((word >> 8) as u8);
+                        bytes.push((word & 0xFF) as u8);
+                    }
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Evaluates an expression consisting of numbers and symbols, which are combined
+    /// using `+` and `-`.
+    fn evaluate(
+        &self,
+        location: &Location,
+        expression: &str,
+        depth: usize,
+    ) -> Result<i64, AssemblerError> {
+        let invalid =
+            || AssemblerError::InvalidExpression(location.clone(), expression.to_string());
+
+        let mut result = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+        let mut is_first_term = true;
+        for c in expression.chars().chain(std::iter::once('+')) {
+            match c {
+                '+' | '-' => {
+                    // only the first term may be empty, which allows a leading sign
+                    let term_value = match term.trim() {
+                        "" if is_first_term => 0,
+                        "" => return Err(invalid()),
+                        term => self.evaluate_term(location, term, depth)?,
+                    };
+                    result += sign * term_value;
+                    sign = if c == '-' { -1 } else { 1 };
+                    term.clear();
+                    is_first_term = false;
+                }
+                c => term.push(c),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Evaluates a single number or symbol.
+    fn evaluate_term(
+        &self,
+        location: &Location,
+        term: &str,
+        depth: usize,
+    ) -> Result<i64, AssemblerError> {
+        if let Some(value) = parse_number(term) {
+            return Ok(value);
+        }
+        if !is_symbol(term) {
+            return Err(AssemblerError::InvalidExpression(
+                location.clone(),
+                term.to_string(),
+            ));
+        }
+        match self.symbols.get(&term.to_ascii_uppercase()) {
+            Some(Symbol::Label(address)) => Ok(*address as i64),
+            Some(Symbol::Constant(expression, constant_location)) => {
+                if depth >= MAX_SYMBOL_DEPTH {
+                    return Err(AssemblerError::InvalidExpression(
+                        constant_location.clone(),
+                        expression.clone(),
+                    ));
+                }
+                self.evaluate(constant_location, expression, depth + 1)
+            }
+            None => Err(AssemblerError::UnknownSymbol(
+                location.clone(),
+                term.to_string(),
+            )),
+        }
+    }
+
+    /// Encodes the instruction given by `mnemonic` and `operands`.
+    fn encode(
+        &self,
+        location: &Location,
+        mnemonic: &str,
+        operands: &[String],
+    ) -> Result<Vec<u8>, AssemblerError> {
+        let value = |expression: &str, max: i64| {
+            in_range(location, self.evaluate(location, expression, 0)?, max)
+        };
+        let operands: Vec<Operand> = operands
+            .iter()
+            .map(|operand| parse_operand(operand))
+            .collect();
+
+        use Operand::*;
+        let opcode: u16 = match (mnemonic, operands.as_slice()) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCD", [Value(n)]) => 0x00C0 | value(n, 0xF)?,
+            ("SCU", [Value(n)]) => 0x00D0 | value(n, 0xF)?,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("EXIT", []) => 0x00FD,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("JP", [Value(a)]) => 0x1000 | value(a, 0xFFF)?,
+            ("JP", [Register(0), Value(a)]) => 0xB000 | value(a, 0xFFF)?,
+            ("CALL", [Value(a)]) => 0x2000 | value(a, 0xFFF)?,
+            ("SE", [Register(x), Value(b)]) => 0x3000 | x_(*x) | value(b, 0xFF)?,
+            ("SNE", [Register(x), Value(b)]) => 0x4000 | x_(*x) | value(b, 0xFF)?,
+            ("SE", [Register(x), Register(y)]) => 0x5000 | x_(*x) | y_(*y),
+            ("SAVE", [Register(x), Register(y)]) => 0x5002 | x_(*x) | y_(*y),
+            ("LOAD", [Register(x), Register(y)]) => 0x5003 | x_(*x) | y_(*y),
+            ("LD", [Register(x), Value(b)]) => 0x6000 | x_(*x) | value(b, 0xFF)?,
+            ("ADD", [Register(x), Value(b)]) => 0x7000 | x_(*x) | value(b, 0xFF)?,
+            ("LD", [Register(x), Register(y)]) => 0x8000 | x_(*x) | y_(*y),
+            ("OR", [Register(x), Register(y)]) => 0x8001 | x_(*x) | y_(*y),
+            ("AND", [Register(x), Register(y)]) => 0x8002 | x_(*x) | y_(*y),
+            ("XOR", [Register(x), Register(y)]) => 0x8003 | x_(*x) | y_(*y),
+            ("ADD", [Register(x), Register(y)]) => 0x8004 | x_(*x) | y_(*y),
+            ("SUB", [Register(x), Register(y)]) => 0x8005 | x_(*x) | y_(*y),
+            ("SHR", [Register(x), Register(y)]) => 0x8006 | x_(*x) | y_(*y),
+            ("SHR", [Register(x)]) => 0x8006 | x_(*x) | y_(*x),
+            ("SUBN", [Register(x), Register(y)]) => 0x8007 | x_(*x) | y_(*y),
+            ("SHL", [Register(x), Register(y)]) => 0x800E | x_(*x) | y_(*y),
+            ("SHL", [Register(x)]) => 0x800E | x_(*x) | y_(*x),
+            ("SNE", [Register(x), Register(y)]) => 0x9000 | x_(*x) | y_(*y),
+            ("LD", [Index, Value(a)]) => 0xA000 | value(a, 0xFFF)?,
+            ("LD", [Index, Long(a)]) => {
+                let address = value(a, 0xFFFF)?;
+                return Ok(vec![
+                    0xF0,
+                    0x00,
+                    (address >> 8) as u8,
+                    (address & 0xFF) as u8,
+                ]);
+            }
+            ("RND", [Register(x), Value(b)]) => 0xC000 | x_(*x) | value(b, 0xFF)?,
+            ("DRW", [Register(x), Register(y), Value(n)]) => {
+                0xD000 | x_(*x) | y_(*y) | value(n, 0xF)?
+            }
+            ("SKP", [Register(x)]) => 0xE09E | x_(*x),
+            ("SKNP", [Register(x)]) => 0xE0A1 | x_(*x),
+            ("PLANE", [Value(n)]) => 0xF001 | value(n, 0x3)? << 8,
+            ("AUDIO", []) => 0xF002,
+            ("LD", [Register(x), DelayTimer]) => 0xF007 | x_(*x),
+            ("LD", [Register(x), Key]) => 0xF00A | x_(*x),
+            ("LD", [DelayTimer, Register(x)]) => 0xF015 | x_(*x),
+            ("LD", [SoundTimer, Register(x)]) => 0xF018 | x_(*x),
+            ("ADD", [Index, Register(x)]) => 0xF01E | x_(*x),
+            ("LD", [Font, Register(x)]) => 0xF029 | x_(*x),
+            ("LD", [BigFont, Register(x)]) => 0xF030 | x_(*x),
+            ("LD", [Bcd, Register(x)]) => 0xF033 | x_(*x),
+            ("PITCH", [Register(x)]) => 0xF03A | x_(*x),
+            ("LD", [IndirectIndex, Register(x)]) => 0xF055 | x_(*x),
+            ("LD", [Register(x), IndirectIndex]) => 0xF065 | x_(*x),
+            ("LD", [Rpl, Register(x)]) => 0xF075 | x_(*x),
+            ("LD", [Register(x), Rpl]) => 0xF085 | x_(*x),
+            (
+                "CLS" | "RET" | "SCD" | "SCU" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "JP"
+                | "CALL" | "SE" | "SNE" | "SAVE" | "LOAD" | "LD" | "ADD" | "OR" | "AND" | "XOR"
+                | "SUB" | "SHR" | "SUBN" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "PLANE"
+                | "AUDIO" | "PITCH",
+                _,
+            ) => {
+                return Err(AssemblerError::InvalidOperands(
+                    location.clone(),
+                    mnemonic.to_string(),
+                ))
+            }
+            _ => {
+                return Err(AssemblerError::UnknownMnemonic(
+                    location.clone(),
+                    mnemonic.to_string(),
+                ))
+            }
+        };
+        Ok(vec![(opcode >> 8) as u8, (opcode & 0xFF) as u8])
+    }
+}
+
+/// Shifts register `x` into the position of the `X` nibble of an opcode.
+fn x_(x: u8) -> u16 {
+    (x as u16) << 8
+}
+
+/// Shifts register `y` into the position of the `Y` nibble of an opcode.
+fn y_(y: u8) -> u16 {
+    (y as u16) << 4
+}
+
+/// Returns `value` as u16 if it is in the range from 0 to `max` (inclusive), and
+/// an error otherwise.
+fn in_range(location: &Location, value: i64, max: i64) -> Result<u16, AssemblerError> {
+    if value < 0 || value > max {
+        return Err(AssemblerError::ValueOutOfRange(location.clone(), value));
+    }
+    Ok(value as u16)
+}
+
+/// Removes a comment starting with `;` from the given line.
+fn strip_comment(line: &str) -> &str {
+    match line.find(';') {
+        Some(start) => &line[..start],
+        None => line,
+    }
+}
+
+/// Splits off the first whitespace separated word of `line`.
+fn split_first_word(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.find(char::is_whitespace) {
+        Some(end) => (&line[..end], line[end..].trim()),
+        None => (line, ""),
+    }
+}
+
+/// Splits the given comma separated operands.
+fn split_operands(operands: &str) -> Vec<String> {
+    if operands.trim().is_empty() {
+        return vec![];
+    }
+    operands
+        .split(',')
+        .map(|operand| operand.trim().to_string())
+        .collect()
+}
+
+/// Returns whether `name` is a valid symbol name. Symbol names start with a letter
+/// or an underscore, followed by letters, digits and underscores.
+fn is_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Parses a hexadecimal (`0x1F`), binary (`0b11111`) or decimal (`31`) number.
+fn parse_number(term: &str) -> Option<i64> {
+    let lower = term.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Parses an operand. Every operand that is not a register or another reserved
+/// name is interpreted as an expression.
+fn parse_operand(operand: &str) -> Operand {
+    let upper = operand.to_ascii_uppercase();
+    match upper.as_str() {
+        "I" => return Operand::Index,
+        "[I]" => return Operand::IndirectIndex,
+        "DT" => return Operand::DelayTimer,
+        "ST" => return Operand::SoundTimer,
+        "K" => return Operand::Key,
+        "F" => return Operand::Font,
+        "HF" => return Operand::BigFont,
+        "B" => return Operand::Bcd,
+        "R" => return Operand::Rpl,
+        _ => {}
+    }
+    if upper.len() == 2 && upper.starts_with('V') {
+        if let Ok(register) = u8::from_str_radix(&upper[1..], 16) {
+            return Operand::Register(register);
+        }
+    }
+    let (first, rest) = split_first_word(operand);
+    if first.eq_ignore_ascii_case("LONG") {
+        return Operand::Long(rest.to_string());
+    }
+    Operand::Value(operand.to_string())
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+impl std::fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AssemblerError::CouldNotReadFile(path) => write!(f, "Could not read file: {:?}", path),
+            AssemblerError::UnknownMnemonic(location, mnemonic) => {
+                write!(f, "{}: Unknown mnemonic {:?}.", location, mnemonic)
+            }
+            AssemblerError::InvalidOperands(location, statement) => {
+                write!(f, "{}: Invalid operands for {:?}.", location, statement)
+            }
+            AssemblerError::UnknownSymbol(location, symbol) => {
+                write!(f, "{}: Unknown symbol {:?}.", location, symbol)
+            }
+            AssemblerError::DuplicateSymbol(location, symbol) => {
+                write!(f, "{}: Symbol {:?} is already defined.", location, symbol)
+            }
+            AssemblerError::InvalidExpression(location, expression) => {
+                write!(f, "{}: Invalid expression {:?}.", location, expression)
+            }
+            AssemblerError::ValueOutOfRange(location, value) => {
+                write!(f, "{}: Value {} is out of range.", location, value)
+            }
+            AssemblerError::IncludeDepthExceeded(location) => write!(
+                f,
+                "{}: Includes are nested more than {} levels deep.",
+                location, MAX_INCLUDE_DEPTH
+            ),
+        }
+    }
+}
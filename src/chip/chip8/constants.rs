@@ -1,10 +1,45 @@
-/// Decrementing the timers every cycle may make programs run less smoothly
-/// this constant allows to adjust the timer resolution. That is, the
-/// timers are decremented every `CHIP8_TIMER_RESOLUTION` cycles.
-pub const CHIP8_TIMER_RESOLUTION: u8 = 10;
+/// The address at which programs are loaded into the memory.
+pub const CHIP8_PROGRAM_OFFSET: u16 = 0x200;
 
-/// The maximum size of a CHIP-8 program in bytes.
-pub const CHIP8_MAX_PROGRAM_SIZE: u16 = 3584;
+/// The size of the CHIP-8 memory in bytes.
+pub const CHIP8_MEMORY_SIZE: usize = 4096;
+
+/// The size of the XO-CHIP memory in bytes.
+pub const XO_CHIP_MEMORY_SIZE: usize = 65536;
+
+/// The number of bitplanes of the display. Note that only XO-CHIP programs
+/// can select planes other than the first one.
+pub const XO_CHIP_PLANES: usize = 2;
+
+/// The maximum number of dirty rectangles tracked until the output pins are marked
+/// as displayed. Once there are more, they are merged into their bounding rectangle.
+pub const MAX_DIRTY_RECTS: usize = 16;
+
+/// The length of the XO-CHIP audio pattern buffer in bytes.
+pub const XO_CHIP_AUDIO_PATTERN_LEN: usize = 16;
+
+/// The initial value of the XO-CHIP pitch register, which corresponds to a
+/// playback rate of 4000 Hz.
+pub const XO_CHIP_DEFAULT_PITCH: u8 = 64;
+
+/// The audio pattern played while the sound timer is active if the program did
+/// not load a pattern. At the default pitch, it is a 500 Hz square wave.
+pub const CHIP8_BEEP_PATTERN: [u8; XO_CHIP_AUDIO_PATTERN_LEN] = [0xF0; XO_CHIP_AUDIO_PATTERN_LEN];
+
+/// The width of the display in low resolution mode.
+pub const CHIP8_LORES_WIDTH: usize = 64;
+
+/// The height of the display in low resolution mode.
+pub const CHIP8_LORES_HEIGHT: usize = 32;
+
+/// The width of the display in SUPER-CHIP high resolution mode.
+pub const SCHIP_HIRES_WIDTH: usize = 128;
+
+/// The height of the display in SUPER-CHIP high resolution mode.
+pub const SCHIP_HIRES_HEIGHT: usize = 64;
+
+/// The number of RPL user flags available to `0xFX75` and `0xFX85`.
+pub const SCHIP_RPL_FLAGS: usize = 16;
 
 /// The offset at which the predefined CHIP-8 charset is loaded into
 /// the memory.
@@ -32,3 +67,42 @@ pub const CHIP8_CHARSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
+
+/// The offset at which the predefined SUPER-CHIP big charset is loaded into
+/// the memory. It is located directly after the CHIP-8 charset.
+pub const SCHIP_BIG_CHARSET_OFFSET: u16 = CHIP8_CHARSET_OFFSET + CHIP8_CHARSET_LEN;
+
+/// The length of the predefined SUPER-CHIP big charset.
+pub const SCHIP_BIG_CHARSET_LEN: u16 = 160;
+
+/// The predefined SUPER-CHIP big charset. Each character is 8 pixels wide and
+/// 10 pixels high.
+pub const SCHIP_BIG_CHARSET: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// The magic bytes identifying a save state.
+pub const SAVE_STATE_MAGIC: [u8; 4] = *b"C8ST";
+
+/// The version of the save state format. It has to be incremented whenever the
+/// format changes.
+pub const SAVE_STATE_VERSION: u16 = 4;
+
+/// The length of the save state header, consisting of the magic bytes, the
+/// version and the length of the payload.
+pub const SAVE_STATE_HEADER_LEN: usize = 10;
@@ -0,0 +1,152 @@
+use crate::chip::{
+    chip8::cursive_display::{Display, DISPLAY_NAME},
+    chip8::disassembler::disassemble_at,
+    chip8::Chip8,
+    ChipWithCursiveDebugger,
+};
+
+use cursive::{
+    view::Nameable,
+    views::{LinearLayout, Panel, TextView},
+    CbSink,
+};
+
+/// The name of the view showing the registers, timers, and the stack.
+pub const REGISTERS_NAME: &str = "registers";
+
+/// The name of the view showing the disassembly around the program counter.
+pub const DISASSEMBLY_NAME: &str = "disassembly";
+
+/// The name of the view showing the memory around the index register.
+pub const MEMORY_NAME: &str = "memory";
+
+/// The number of instructions shown before the program counter.
+const DISASSEMBLY_LINES_BEFORE: u16 = 8;
+
+/// The total number of instructions shown in the disassembly.
+const DISASSEMBLY_LINES: usize = 20;
+
+/// The number of rows of 16 bytes shown in the memory view.
+const MEMORY_ROWS: usize = 16;
+
+/// Creates the debugger layout, which consists of the display along with panes
+/// for the registers, the disassembly, and the memory. The panes are updated
+/// by `update_debugger_ui`, and the display is updated by `update_ui`.
+pub fn debugger_layout() -> LinearLayout {
+    LinearLayout::vertical()
+        .child(
+            LinearLayout::horizontal()
+                .child(Panel::new(Display::default().with_name(DISPLAY_NAME)).title("Display"))
+                .child(Panel::new(TextView::empty().with_name(REGISTERS_NAME)).title("Registers")),
+        )
+        .child(
+            LinearLayout::horizontal()
+                .child(
+                    Panel::new(TextView::empty().with_name(DISASSEMBLY_NAME)).title("Disassembly"),
+                )
+                .child(Panel::new(TextView::empty().with_name(MEMORY_NAME)).title("Memory at I")),
+        )
+}
+
+impl Chip8 {
+    /// Formats the registers, the timers and the stack, where the stack is listed
+    /// from the bottom to the top.
+    fn format_registers(&self) -> String {
+        let mut lines = vec![];
+        for i in 0..8 {
+            lines.push(format!(
+                "V{:X} {:02X}   V{:X} {:02X}",
+                i,
+                self.registers[i],
+                i + 8,
+                self.registers[i + 8]
+            ));
+        }
+        lines.push(String::new());
+        lines.push(format!("I  {:#06X}", self.index));
+        lines.push(format!("PC {:#06X}", self.program_counter));
+        lines.push(format!("SP {}", self.stack_pointer));
+        lines.push(format!(
+            "DT {:02X}   ST {:02X}",
+            self.delay_timer, self.sound_timer
+        ));
+        lines.push(String::new());
+        lines.push("Stack".to_string());
+        for (i, address) in self.stack[..self.stack_pointer as usize].iter().enumerate() {
+            lines.push(format!("{:2} {:#06X}", i, address));
+        }
+        lines.join("\n")
+    }
+
+    /// Formats the instructions around the program counter, marking the instruction
+    /// at the program counter. Instructions before the program counter are decoded
+    /// as two byte instructions, because the start of an instruction cannot be
+    /// determined when decoding backwards.
+    fn format_disassembly(&self) -> String {
+        let program_counter = self.program_counter;
+        let mut address = program_counter.saturating_sub(2 * DISASSEMBLY_LINES_BEFORE);
+        let mut lines = vec![];
+        while lines.len() < DISASSEMBLY_LINES {
+            let bytes = if address < program_counter {
+                &self.memory[..self.memory.len().min(program_counter as usize)]
+            } else {
+                &self.memory[..]
+            };
+            let instruction = match disassemble_at(bytes, 0, address) {
+                Some(instruction) => instruction,
+                None => break,
+            };
+            let raw: String = instruction
+                .bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            lines.push(format!(
+                "{} {:#06X}  {:<8}  {}",
+                if address == program_counter { ">" } else { " " },
+                address,
+                raw,
+                instruction.mnemonic.as_deref().unwrap_or("???")
+            ));
+            address = match address.checked_add(instruction.size()) {
+                Some(address) => address,
+                None => break,
+            };
+        }
+        lines.join("\n")
+    }
+
+    /// Formats the memory starting at the row containing the index register.
+    fn format_memory(&self) -> String {
+        let start = self.memory_index(self.index) & !0xF;
+        self.memory[start..]
+            .chunks(16)
+            .take(MEMORY_ROWS)
+            .enumerate()
+            .map(|(row, bytes)| {
+                let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                format!("{:#06X}  {}", start + 16 * row, hex.join(" "))
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl ChipWithCursiveDebugger for Chip8 {
+    fn update_debugger_ui(&self, gfx_sink: &CbSink) {
+        let registers = self.format_registers();
+        let disassembly = self.format_disassembly();
+        let memory = self.format_memory();
+        gfx_sink
+            .send(Box::new(move |s: &mut cursive::Cursive| {
+                s.call_on_name(REGISTERS_NAME, |view: &mut TextView| {
+                    view.set_content(registers)
+                });
+                s.call_on_name(DISASSEMBLY_NAME, |view: &mut TextView| {
+                    view.set_content(disassembly)
+                });
+                s.call_on_name(MEMORY_NAME, |view: &mut TextView| view.set_content(memory));
+            }))
+            .expect("Sending updated debugger failed");
+    }
+}
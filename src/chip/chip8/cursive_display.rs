@@ -1,4 +1,11 @@
-use crate::chip::{chip8::Chip8, Chip, ChipWithCursiveDisplay};
+use crate::chip::{
+    chip8::{
+        constants::{CHIP8_LORES_HEIGHT, CHIP8_LORES_WIDTH},
+        Chip8,
+    },
+    framebuffer::Framebuffer,
+    Chip, ChipWithCursiveDisplay,
+};
 
 use cursive::{
     direction::Direction,
@@ -8,24 +15,124 @@ use cursive::{
     CbSink, Printer, Vec2,
 };
 
+/// The colors used to draw pixels, indexed by the color of a pixel. A pixel's
+/// color is determined by the bitplanes it is set in, where bit `i` of the color
+/// corresponds to bitplane `i`.
+pub type Palette = [Color; 4];
+
+/// The built-in themes. The `default` theme draws pixels of color `0` in the
+/// background color of the terminal and is meant for light terminals, whereas
+/// `inverted` is meant for dark terminals. The other themes imitate monochrome
+/// monitors and draw their own background.
+const THEMES: [(&str, Palette); 4] = [
+    (
+        "default",
+        [
+            Color::TerminalDefault,
+            Color::RgbLowRes(0, 0, 0),
+            Color::RgbLowRes(4, 1, 0),
+            Color::RgbLowRes(2, 2, 2),
+        ],
+    ),
+    (
+        "inverted",
+        [
+            Color::TerminalDefault,
+            Color::RgbLowRes(5, 5, 5),
+            Color::RgbLowRes(4, 1, 0),
+            Color::RgbLowRes(2, 2, 2),
+        ],
+    ),
+    (
+        "green",
+        [
+            Color::RgbLowRes(0, 1, 0),
+            Color::RgbLowRes(1, 5, 1),
+            Color::RgbLowRes(0, 3, 0),
+            Color::RgbLowRes(0, 4, 1),
+        ],
+    ),
+    (
+        "amber",
+        [
+            Color::RgbLowRes(1, 0, 0),
+            Color::RgbLowRes(5, 3, 0),
+            Color::RgbLowRes(3, 1, 0),
+            Color::RgbLowRes(4, 2, 0),
+        ],
+    ),
+];
+
+/// Returns the palette of the built-in theme called `name`, which is one of
+/// `default`, `inverted`, `green` and `amber`.
+pub fn theme(name: &str) -> Option<Palette> {
+    THEMES
+        .iter()
+        .find(|(theme, _)| theme.eq_ignore_ascii_case(name))
+        .map(|(_, palette)| *palette)
+}
+
+/// Returns the names of the built-in themes.
+pub fn theme_names() -> Vec<&'static str> {
+    THEMES.iter().map(|(name, _)| *name).collect()
+}
+
+/// The name of the view showing the display. `update_ui` replaces the pixels of
+/// the view with this name, so the display has to be added to the UI using
+/// `Display::default().with_name(DISPLAY_NAME)`.
+pub const DISPLAY_NAME: &str = "display";
+
 /// Represents the display of the CHIP-8
 pub struct Display {
-    pixels: [bool; 64 * 32],
+    pixels: Vec<u8>,
+    width: usize,
+    height: usize,
+    palette: Palette,
 }
 
 impl Display {
-    /// Creates a new display from a slice.
-    pub fn new(pixels: &[bool]) -> Self {
-        assert_eq!(pixels.len(), 64 * 32);
-        let mut tmp = [false; 64 * 32];
-        tmp.copy_from_slice(&pixels[..]);
-        Display { pixels: tmp }
+    /// Creates a new display showing the pixels of `framebuffer`.
+    ///
+    /// # Panics
+    /// In case `framebuffer` has more than two bitplanes, as a palette only holds
+    /// four colors.
+    pub fn new(framebuffer: &Framebuffer) -> Self {
+        assert!(framebuffer.planes() <= 2);
+        Display {
+            pixels: framebuffer.pixels().to_vec(),
+            width: framebuffer.width(),
+            height: framebuffer.height(),
+            palette: theme("default").unwrap(),
+        }
+    }
+
+    /// Copies the pixels within the dirty rectangles of `framebuffer` to the
+    /// display. All pixels are replaced if the size of the framebuffer differs from
+    /// the size of the display.
+    pub fn update(&mut self, framebuffer: &Framebuffer) {
+        if (self.width, self.height) != (framebuffer.width(), framebuffer.height()) {
+            *self = Display::new(framebuffer).with_palette(self.palette);
+            return;
+        }
+        for rect in framebuffer.dirty_rects() {
+            for y in rect.y..rect.y + rect.height {
+                let row = rect.x + y * self.width..rect.x + rect.width + y * self.width;
+                self.pixels[row.clone()].copy_from_slice(&framebuffer.pixels()[row]);
+            }
+        }
+    }
+
+    /// Sets the palette used to draw the pixels, which is kept when the pixels are
+    /// updated.
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
     }
 }
 
 impl Default for Display {
     fn default() -> Self {
-        Self::new(&[false; 64 * 32])
+        Self::new(&Framebuffer::new(CHIP8_LORES_WIDTH, CHIP8_LORES_HEIGHT, 1))
     }
 }
 
@@ -33,18 +140,20 @@ impl Default for Display {
 /// as a View out of the box.
 impl View for Display {
     fn draw(&self, printer: &Printer) {
-        printer.with_color(
-            ColorStyle::new(Color::Dark(BaseColor::Black), Color::RgbLowRes(0, 0, 0)),
-            |printer| {
-                for x in 0..64 {
-                    for y in 0..32 {
-                        if self.pixels[x + 64 * y] {
-                            printer.print((x, y), " ");
+        for (color, background) in self.palette.iter().enumerate() {
+            printer.with_color(
+                ColorStyle::new(Color::Dark(BaseColor::Black), *background),
+                |printer| {
+                    for x in 0..self.width {
+                        for y in 0..self.height {
+                            if self.pixels[x + self.width * y] as usize == color {
+                                printer.print((x, y), " ");
+                            }
                         }
                     }
-                }
-            },
-        );
+                },
+            );
+        }
     }
 
     fn take_focus(&mut self, _: Direction) -> bool {
@@ -56,26 +165,24 @@ impl View for Display {
     }
 
     fn required_size(&mut self, _: Vec2) -> Vec2 {
-        Vec2 { x: 64, y: 32 }
+        Vec2 {
+            x: self.width,
+            y: self.height,
+        }
     }
 }
 
 impl ChipWithCursiveDisplay for Chip8 {
     fn update_ui(&mut self, gfx_sink: &CbSink) {
-        fn get_display(chip: &Chip8) -> Display {
-            Display::new(chip.read_output_pins())
-        }
-
-        if !self.draw {
+        if self.dirty_rects.is_empty() {
             return;
         }
-        let display = get_display(&self);
+        let framebuffer = self.framebuffer();
         gfx_sink
             .send(Box::new(Box::new(move |s: &mut cursive::Cursive| {
-                s.pop_layer();
-                s.add_layer(display);
+                s.call_on_name(DISPLAY_NAME, |view: &mut Display| view.update(&framebuffer));
             })))
             .expect("Sending updated display failed");
-        self.draw = false;
+        self.clear_dirty_rects();
     }
 }
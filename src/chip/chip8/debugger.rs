@@ -0,0 +1,384 @@
+use std::str::FromStr;
+
+use crate::chip::chip8::Chip8;
+
+/// Represents a condition that stops the execution before an instruction is
+/// executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Stops before the instruction at the given address is executed.
+    Address(u16),
+
+    /// Stops before an instruction is executed whose opcode matches the pattern
+    /// in all nibbles that are set in the mask. Holds the pattern and the mask,
+    /// e.g., `Opcode(0xD000, 0xF000)` stops before every `0xDXYN`.
+    Opcode(u16, u16),
+}
+
+/// Represents a condition that stops the execution after an instruction accessed
+/// the memory or changed a register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// Stops after an instruction read from the given range of addresses (both
+    /// inclusive). Note that fetching instructions does not count as reading.
+    Read(u16, u16),
+
+    /// Stops after an instruction wrote to the given range of addresses (both
+    /// inclusive).
+    Write(u16, u16),
+
+    /// Stops after an instruction changed the given register.
+    Register(u8),
+}
+
+/// Describes why the execution stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The breakpoint was hit at the given address. The instruction at this
+    /// address has not been executed yet.
+    Breakpoint(Breakpoint, u16),
+
+    /// The watchpoint was hit by the instruction at the given address, which
+    /// read from the given memory address.
+    MemoryRead(Watchpoint, u16, u16),
+
+    /// The watchpoint was hit by the instruction at the given address, which
+    /// wrote to the given memory address.
+    MemoryWrite(Watchpoint, u16, u16),
+
+    /// The watchpoint was hit by the instruction at the given address, which
+    /// changed the watched register from the first to the second value.
+    RegisterChanged(Watchpoint, u16, u8, u8),
+}
+
+/// Error type for errors that occur during parsing breakpoints and watchpoints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The given string does not describe a breakpoint.
+    InvalidBreakpoint(String),
+    /// The given string does not describe a watchpoint.
+    InvalidWatchpoint(String),
+}
+
+/// Holds the breakpoints and watchpoints of a machine, along with the state
+/// required to check them during the execution.
+#[derive(Default)]
+pub(super) struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+
+    /// The address of the instruction currently being executed.
+    instruction_address: u16,
+
+    /// The address at which breakpoints are ignored once, so that the execution
+    /// can resume after stopping at a breakpoint.
+    resume_address: Option<u16>,
+
+    /// The reason why the execution stopped, if it stopped.
+    stop_reason: Option<StopReason>,
+}
+
+impl Debugger {
+    /// Moves the breakpoints and watchpoints of `other` into a new debugger. This
+    /// is used to keep them when the state of the machine is replaced.
+    pub(super) fn take_points(other: &mut Debugger) -> Self {
+        Debugger {
+            breakpoints: std::mem::take(&mut other.breakpoints),
+            watchpoints: std::mem::take(&mut other.watchpoints),
+            ..Debugger::default()
+        }
+    }
+
+    /// Checks the breakpoints before the instruction at `address` with the given
+    /// `opcode` is executed. Returns whether the execution stops.
+    pub(super) fn stops_before(&mut self, address: u16, opcode: u16) -> bool {
+        self.instruction_address = address;
+        if self.resume_address.take() == Some(address) {
+            return false;
+        }
+
+        let hit = self.breakpoints.iter().find(|breakpoint| match breakpoint {
+            Breakpoint::Address(breakpoint_address) => *breakpoint_address == address,
+            Breakpoint::Opcode(pattern, mask) => opcode & mask == pattern & mask,
+        });
+        match hit {
+            Some(breakpoint) => {
+                self.stop(StopReason::Breakpoint(*breakpoint, address));
+                self.resume_address = Some(address);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks the watchpoints for a read from the memory at `address`.
+    pub(super) fn on_read(&mut self, address: u16) {
+        let instruction_address = self.instruction_address;
+        let hit = self
+            .watchpoints
+            .iter()
+            .find_map(|watchpoint| match watchpoint {
+                Watchpoint::Read(start, end) if *start <= address && address <= *end => Some(
+                    StopReason::MemoryRead(*watchpoint, instruction_address, address),
+                ),
+                _ => None,
+            });
+        if let Some(reason) = hit {
+            self.stop(reason);
+        }
+    }
+
+    /// Checks the watchpoints for a write to the memory at `address`.
+    pub(super) fn on_write(&mut self, address: u16) {
+        let instruction_address = self.instruction_address;
+        let hit = self
+            .watchpoints
+            .iter()
+            .find_map(|watchpoint| match watchpoint {
+                Watchpoint::Write(start, end) if *start <= address && address <= *end => Some(
+                    StopReason::MemoryWrite(*watchpoint, instruction_address, address),
+                ),
+                _ => None,
+            });
+        if let Some(reason) = hit {
+            self.stop(reason);
+        }
+    }
+
+    /// Checks the watchpoints for changes of the registers by the instruction that
+    /// was just executed, where `before` holds the registers before the execution.
+    pub(super) fn on_registers(&mut self, before: &[u8; 16], after: &[u8; 16]) {
+        let instruction_address = self.instruction_address;
+        let hit = self
+            .watchpoints
+            .iter()
+            .find_map(|watchpoint| match watchpoint {
+                Watchpoint::Register(reg) if before[*reg as usize] != after[*reg as usize] => {
+                    Some(StopReason::RegisterChanged(
+                        *watchpoint,
+                        instruction_address,
+                        before[*reg as usize],
+                        after[*reg as usize],
+                    ))
+                }
+                _ => None,
+            });
+        if let Some(reason) = hit {
+            self.stop(reason);
+        }
+    }
+
+    /// Records `reason` unless the execution already stopped for another reason.
+    fn stop(&mut self, reason: StopReason) {
+        if self.stop_reason.is_none() {
+            self.stop_reason = Some(reason);
+        }
+    }
+
+    /// Returns and clears the reason why the execution stopped.
+    pub(super) fn take_stop_reason(&mut self) -> Option<StopReason> {
+        self.stop_reason.take()
+    }
+}
+
+impl Chip8 {
+    /// Adds a breakpoint. Once it is hit, `Chip::take_stop_reason` reports it,
+    /// and the instruction at the program counter is not executed by the cycle.
+    /// The next cycle resumes the execution.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.debugger.breakpoints.contains(&breakpoint) {
+            self.debugger.breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Removes a breakpoint. Returns whether the breakpoint existed.
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        let len = self.debugger.breakpoints.len();
+        self.debugger.breakpoints.retain(|b| *b != breakpoint);
+        self.debugger.breakpoints.len() != len
+    }
+
+    /// Returns the breakpoints.
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.debugger.breakpoints
+    }
+
+    /// Adds a watchpoint. Once it is hit, `Chip::take_stop_reason` reports it
+    /// after the cycle executing the instruction that hit the watchpoint.
+    ///
+    /// # Panics
+    /// In case a register watchpoint refers to a register other than `V0` to `VF`.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if let Watchpoint::Register(reg) = watchpoint {
+            assert!(reg & 0x0F == reg);
+        }
+        if !self.debugger.watchpoints.contains(&watchpoint) {
+            self.debugger.watchpoints.push(watchpoint);
+        }
+    }
+
+    /// Removes a watchpoint. Returns whether the watchpoint existed.
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let len = self.debugger.watchpoints.len();
+        self.debugger.watchpoints.retain(|w| *w != watchpoint);
+        self.debugger.watchpoints.len() != len
+    }
+
+    /// Returns the watchpoints.
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.debugger.watchpoints
+    }
+}
+
+/// Parses an address given in hexadecimal notation with a `0x` prefix.
+fn parse_address(s: &str) -> Option<u16> {
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X"))?;
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Parses a range of addresses given as `0x300` or `0x300-0x30F`.
+fn parse_range(s: &str) -> Option<(u16, u16)> {
+    let (start, end) = match s.find('-') {
+        Some(dash) => (parse_address(&s[..dash])?, parse_address(&s[dash + 1..])?),
+        None => (parse_address(s)?, parse_address(s)?),
+    };
+    if start > end {
+        return None;
+    }
+    Some((start, end))
+}
+
+impl FromStr for Breakpoint {
+    type Err = ParseError;
+
+    /// Parses a breakpoint, which is either an address (e.g., `0x2A4`) or an opcode
+    /// pattern of four nibbles, where each nibble is either a hexadecimal digit or
+    /// a wildcard given as `_` or as a letter other than `A` to `F` (e.g., `DXYN`
+    /// or `00E0`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseError::InvalidBreakpoint(s.to_string());
+        if s.starts_with("0x") || s.starts_with("0X") {
+            return parse_address(s)
+                .map(Breakpoint::Address)
+                .ok_or_else(invalid);
+        }
+        if s.chars().count() != 4 {
+            return Err(invalid());
+        }
+
+        let (mut pattern, mut mask) = (0, 0);
+        for c in s.chars() {
+            pattern <<= 4;
+            mask <<= 4;
+            match c.to_digit(16) {
+                Some(digit) => {
+                    pattern |= digit as u16;
+                    mask |= 0xF;
+                }
+                None if c == '_' || c.is_ascii_alphabetic() => {}
+                None => return Err(invalid()),
+            }
+        }
+        Ok(Breakpoint::Opcode(pattern, mask))
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = ParseError;
+
+    /// Parses a watchpoint, which is either `read:` or `write:` followed by an
+    /// address or a range of addresses (e.g., `read:0x300` or `write:0x300-0x30F`),
+    /// or a register (e.g., `VA`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseError::InvalidWatchpoint(s.to_string());
+        if let Some(range) = s.strip_prefix("read:") {
+            let (start, end) = parse_range(range).ok_or_else(invalid)?;
+            return Ok(Watchpoint::Read(start, end));
+        }
+        if let Some(range) = s.strip_prefix("write:") {
+            let (start, end) = parse_range(range).ok_or_else(invalid)?;
+            return Ok(Watchpoint::Write(start, end));
+        }
+        match s.strip_prefix('V').or_else(|| s.strip_prefix('v')) {
+            Some(reg) if reg.len() == 1 => u8::from_str_radix(reg, 16)
+                .map(Watchpoint::Register)
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Breakpoint::Address(address) => write!(f, "{:#05X}", address),
+            Breakpoint::Opcode(pattern, mask) => {
+                for shift in [12, 8, 4, 0].iter() {
+                    if (mask >> shift) & 0xF == 0 {
+                        write!(f, "_")?;
+                    } else {
+                        write!(f, "{:X}", (pattern >> shift) & 0xF)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (kind, start, end) = match self {
+            Watchpoint::Read(start, end) => ("read", start, end),
+            Watchpoint::Write(start, end) => ("write", start, end),
+            Watchpoint::Register(reg) => return write!(f, "V{:X}", reg),
+        };
+        if start == end {
+            write!(f, "{}:{:#05X}", kind, start)
+        } else {
+            write!(f, "{}:{:#05X}-{:#05X}", kind, start, end)
+        }
+    }
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StopReason::Breakpoint(breakpoint, address) => {
+                write!(f, "Breakpoint {} hit at address {:#05X}.", breakpoint, address)
+            }
+            StopReason::MemoryRead(watchpoint, address, memory_address) => write!(
+                f,
+                "Watchpoint {} hit. The instruction at address {:#05X} read from {:#05X}.",
+                watchpoint, address, memory_address
+            ),
+            StopReason::MemoryWrite(watchpoint, address, memory_address) => write!(
+                f,
+                "Watchpoint {} hit. The instruction at address {:#05X} wrote to {:#05X}.",
+                watchpoint, address, memory_address
+            ),
+            StopReason::RegisterChanged(watchpoint, address, old, new) => write!(
+                f,
+                "Watchpoint {} hit. The instruction at address {:#05X} changed it from {:#04X} to {:#04X}.",
+                watchpoint, address, old, new
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseError::InvalidBreakpoint(s) => write!(
+                f,
+                "Invalid breakpoint {:?}. Expected an address (e.g., 0x2A4) or an opcode pattern (e.g., DXYN).",
+                s
+            ),
+            ParseError::InvalidWatchpoint(s) => write!(
+                f,
+                "Invalid watchpoint {:?}. Expected read:<range>, write:<range> (e.g., write:0x300-0x30F) or a register (e.g., VA).",
+                s
+            ),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::chip::chip8::opcodes::DecodedInstruction;
+
+/// The number of addresses covered by a page of the cache.
+const PAGE_SIZE: usize = 256;
+
+/// The decoded instructions of a page, indexed by the offset of their address
+/// within the page.
+type Page = [Option<DecodedInstruction>; PAGE_SIZE];
+
+/// Caches the instructions decoded from the memory, indexed by the address they
+/// start at. The cache is split into pages which are only allocated once an
+/// instruction within them is decoded, so that constructing a machine stays cheap
+/// even for the 64KB memory of the XO-CHIP.
+pub(super) struct DecodeCache {
+    pages: Vec<Option<Box<Page>>>,
+}
+
+impl DecodeCache {
+    /// Constructs an empty cache for a memory of `memory_size` bytes.
+    pub(super) fn new(memory_size: usize) -> Self {
+        DecodeCache {
+            pages: vec![None; memory_size.div_ceil(PAGE_SIZE)],
+        }
+    }
+
+    /// Returns the instruction decoded at `address`, if any.
+    pub(super) fn get(&self, address: usize) -> Option<DecodedInstruction> {
+        match self.pages.get(address / PAGE_SIZE) {
+            Some(Some(page)) => page[address % PAGE_SIZE],
+            _ => None,
+        }
+    }
+
+    /// Stores the `instruction` decoded at `address`.
+    ///
+    /// # Panics
+    /// In case `address` is not within the memory.
+    pub(super) fn insert(&mut self, address: usize, instruction: DecodedInstruction) {
+        let page =
+            self.pages[address / PAGE_SIZE].get_or_insert_with(|| Box::new([None; PAGE_SIZE]));
+        page[address % PAGE_SIZE] = Some(instruction);
+    }
+
+    /// Removes the instructions whose opcode includes the memory byte at `index`.
+    /// These are the instructions starting at most three bytes before it, as opcodes
+    /// are at most four bytes long.
+    pub(super) fn invalidate(&mut self, index: usize) {
+        for address in index.saturating_sub(3)..=index {
+            if let Some(Some(page)) = self.pages.get_mut(address / PAGE_SIZE) {
+                page[address % PAGE_SIZE] = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip::chip8::{quirks::Quirks, variant::Variant, Chip8};
+    use crate::chip::Chip;
+
+    #[test]
+    fn test_self_modifying_code() {
+        // patches `LD V2, 0x05` at 0x200 to `LD V2, 0x09` using `LD [I], V1`
+        let mut state = Chip8::default();
+        state
+            .load_program(&[
+                0x62, 0x05, // 0x200: LD V2, 0x05
+                0x60, 0x62, // 0x202: LD V0, 0x62
+                0x61, 0x09, // 0x204: LD V1, 0x09
+                0xA2, 0x00, // 0x206: LD I, 0x200
+                0xF1, 0x55, // 0x208: LD [I], V1
+                0x12, 0x00, // 0x20A: JP 0x200
+            ])
+            .expect("Loading program failed");
+        for _ in 0..6 {
+            state.cycle().expect("Cycle failed");
+        }
+        assert_eq!(state.registers[2], 0x05);
+        state.cycle().expect("Cycle failed");
+        assert_eq!(state.registers[2], 0x09);
+
+        // patches `SE V0, 0xFF` at 0x208 to `SE V0, 0x00` using `LD B, V3`, which also
+        // overwrites the jump following it with the skipped `0x0000`
+        let mut state = Chip8::default();
+        state
+            .load_program(&[
+                0xA2, 0x09, // 0x200: LD I, 0x209
+                0x60, 0x00, // 0x202: LD V0, 0x00
+                0x63, 0x00, // 0x204: LD V3, 0x00
+                0x7E, 0x01, // 0x206: ADD VE, 0x01
+                0x30, 0xFF, // 0x208: SE V0, 0xFF
+                0x12, 0x0E, // 0x20A: JP 0x20E
+                0x12, 0x0C, // 0x20C: JP 0x20C
+                0xF3, 0x33, // 0x20E: LD B, V3
+                0x12, 0x06, // 0x210: JP 0x206
+            ])
+            .expect("Loading program failed");
+        for _ in 0..20 {
+            state.cycle().expect("Cycle failed");
+        }
+        assert_eq!(state.program_counter, 0x20C);
+        assert_eq!(state.registers[0xE], 2);
+    }
+
+    #[test]
+    fn test_self_modifying_long_instruction() {
+        // patches the second half of `LD I, LONG 0x0300` at 0x200
+        let mut state = Chip8::with_variant(Variant::XoChip, Quirks::xo_chip());
+        state
+            .load_program(&[
+                0xF0, 0x00, 0x03, 0x00, // 0x200: LD I, LONG 0x0300
+                0x60, 0x12, // 0x204: LD V0, 0x12
+                0x61, 0x34, // 0x206: LD V1, 0x34
+                0xA2, 0x02, // 0x208: LD I, 0x202
+                0xF1, 0x55, // 0x20A: LD [I], V1
+                0x12, 0x00, // 0x20C: JP 0x200
+            ])
+            .expect("Loading program failed");
+        state.cycle().expect("Cycle failed");
+        assert_eq!(state.index, 0x0300);
+        for _ in 0..6 {
+            state.cycle().expect("Cycle failed");
+        }
+        assert_eq!(state.index, 0x1234);
+    }
+}
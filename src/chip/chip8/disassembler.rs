@@ -0,0 +1,122 @@
+use std::collections::BTreeSet;
+
+use crate::chip::chip8::opcodes::Opcode;
+
+/// Represents a single disassembled instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledInstruction {
+    /// The address of the instruction.
+    pub address: u16,
+
+    /// The raw bytes of the instruction. These are four bytes for XO-CHIP
+    /// long instructions, two bytes for all other instructions, and a single
+    /// byte for a trailing byte at the end of a program.
+    pub bytes: Vec<u8>,
+
+    /// The mnemonic of the instruction (e.g., `LD V3, 0x1F`), or `None` if the
+    /// bytes do not represent a supported instruction.
+    pub mnemonic: Option<String>,
+}
+
+impl DisassembledInstruction {
+    /// Returns the size of the instruction in bytes.
+    pub fn size(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    /// Returns the addresses at which execution may continue after this
+    /// instruction. Returns an empty vector for instructions after which the
+    /// successor cannot be determined statically (e.g., `RET` or `JP V0, 0x2A4`),
+    /// as well as for invalid instructions.
+    fn successors(&self, bytes: &[u8], base_address: u16) -> Vec<u16> {
+        if self.mnemonic.is_none() {
+            return vec![];
+        }
+
+        let next = self.address.wrapping_add(self.size());
+        let skip_target = || {
+            let skipped_len = disassemble_at(bytes, base_address, next)
+                .map(|instruction| instruction.size())
+                .unwrap_or(2);
+            next.wrapping_add(skipped_len)
+        };
+        let word = (self.bytes[0] as u16) << 8 | self.bytes[1] as u16;
+        let address = word & 0xFFF;
+
+        match word >> 12 {
+            0x0 if word == 0x00EE || word == 0x00FD => vec![],
+            0x1 => vec![address],
+            0x2 => vec![address, next],
+            0x3 | 0x4 | 0x9 => vec![next, skip_target()],
+            0x5 if word & 0xF == 0 => vec![next, skip_target()],
+            0xB => vec![],
+            0xE => vec![next, skip_target()],
+            _ => vec![next],
+        }
+    }
+}
+
+/// Disassembles the instruction located at `address` of a program given as `bytes`,
+/// which is loaded at `base_address`. Returns `None` if `address` is not located
+/// within the program. Note that `0xF000` is always disassembled as the first half
+/// of an XO-CHIP long instruction, given that it is invalid for other variants.
+pub fn disassemble_at(
+    bytes: &[u8],
+    base_address: u16,
+    address: u16,
+) -> Option<DisassembledInstruction> {
+    let offset = address.checked_sub(base_address)? as usize;
+    match bytes.get(offset..) {
+        None | Some([]) => None,
+        Some([byte]) => Some(DisassembledInstruction {
+            address,
+            bytes: vec![*byte],
+            mnemonic: None,
+        }),
+        Some(remaining) => {
+            let mut opcode = Opcode::new(&[remaining[0], remaining[1]]);
+            if opcode.has_long_operand() && remaining.len() >= 4 {
+                opcode = opcode.with_long_operand(&[remaining[2], remaining[3]]);
+            }
+            let len = opcode.len() as usize;
+            Some(DisassembledInstruction {
+                address,
+                bytes: remaining[..len].to_vec(),
+                mnemonic: opcode.mnemonic(),
+            })
+        }
+    }
+}
+
+/// Disassembles the program given as `bytes`, which is loaded at `base_address`,
+/// by decoding one instruction after the other from the beginning of the program.
+/// Note that this also decodes data embedded in the program as instructions.
+pub fn disassemble(bytes: &[u8], base_address: u16) -> Vec<DisassembledInstruction> {
+    let mut instructions = vec![];
+    let mut address = base_address;
+    while let Some(instruction) = disassemble_at(bytes, base_address, address) {
+        address = address.wrapping_add(instruction.size());
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+/// Returns the addresses of all instructions of the program given as `bytes`,
+/// which is loaded at `base_address`, which are reachable by following the
+/// control flow starting at `base_address`. Note that targets of indirect
+/// jumps (`JP V0, 0x2A4`) cannot be determined statically and are therefore not
+/// considered reachable, unless they are reachable otherwise.
+pub fn reachable_addresses(bytes: &[u8], base_address: u16) -> BTreeSet<u16> {
+    let mut reachable = BTreeSet::new();
+    let mut pending = vec![base_address];
+    while let Some(address) = pending.pop() {
+        if reachable.contains(&address) {
+            continue;
+        }
+        if let Some(instruction) = disassemble_at(bytes, base_address, address) {
+            reachable.insert(address);
+            pending.extend(instruction.successors(bytes, base_address));
+        }
+    }
+    reachable
+}
@@ -0,0 +1,506 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use crate::chip::{
+    chip8::debugger::{Breakpoint, StopReason, Watchpoint},
+    chip8::Chip8,
+    scheduler::Scheduler,
+    Chip,
+};
+
+/// The number of cycles executed between two checks whether the client requested
+/// to interrupt the execution.
+const INTERRUPT_CHECK_INTERVAL: u32 = 1000;
+
+/// The maximum number of bytes that can be read or written by a single packet.
+const MAX_MEMORY_ACCESS_LEN: usize = 0x1000;
+
+/// The byte sent by clients to interrupt the execution.
+const INTERRUPT: u8 = 0x03;
+
+/// The register numbers of the index register, the program counter, the stack
+/// pointer, the delay timer and the sound timer. Registers `V0` to `VF` have the
+/// numbers `0x0` to `0xF`.
+const REGISTER_I: usize = 0x10;
+const REGISTER_PC: usize = 0x11;
+const REGISTER_SP: usize = 0x12;
+const REGISTER_DT: usize = 0x13;
+const REGISTER_ST: usize = 0x14;
+
+/// The number of registers exposed to clients.
+const REGISTER_COUNT: usize = 0x15;
+
+/// Serves a client connected via `stream` using the GDB remote serial protocol,
+/// which allows the client to inspect and control `chip8`. Returns once the client
+/// detaches, kills the program, or disconnects.
+///
+/// The registers `V0` to `VF` are numbered `0x0` to `0xF`, followed by `I`, `PC`,
+/// `SP`, `DT` and `ST`. `I` and `PC` are two bytes wide, all other registers are
+/// a single byte wide. All values are encoded in big-endian byte order.
+/// Supported are the packets `?`, `g`, `G`, `p`, `P`, `m`, `M`, `s`, `c`, `k` and
+/// `D`, as well as software breakpoints (`Z0`/`z0`) and read and write
+/// watchpoints (`Z2`/`z2` and `Z3`/`z3`). The timers are ticked by a scheduler
+/// with the default number of instructions per frame, without limiting the speed
+/// of the execution.
+pub fn serve(chip8: &mut Chip8, stream: TcpStream) -> io::Result<()> {
+    let mut stub = GdbStub {
+        chip8,
+        stream,
+        scheduler: Scheduler::default(),
+        last_packet: String::new(),
+    };
+    while let Some(packet) = stub.receive_packet()? {
+        let reply = match packet.as_bytes().first() {
+            Some(b'k') => return Ok(()),
+            Some(b'D') => {
+                stub.send_packet("OK")?;
+                return Ok(());
+            }
+            _ => stub.handle_packet(&packet)?,
+        };
+        stub.send_packet(&reply)?;
+    }
+    Ok(())
+}
+
+/// Holds the state of a connection to a client.
+struct GdbStub<'a> {
+    chip8: &'a mut Chip8,
+    stream: TcpStream,
+
+    /// Ticks the timers of the chip while it is executed.
+    scheduler: Scheduler,
+
+    /// The last packet sent, which is sent again if the client requests it.
+    last_packet: String,
+}
+
+impl<'a> GdbStub<'a> {
+    /// Receives the next packet and acknowledges it. Returns `None` if the client
+    /// disconnected.
+    fn receive_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(b'-') => {
+                    let packet = self.last_packet.clone();
+                    self.send_packet(&packet)?;
+                    continue;
+                }
+                // acknowledgements and interrupts while halted are ignored
+                Some(_) => continue,
+            }
+
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+            if expected != Some(compute_checksum(&data)) {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+            self.stream.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    /// Sends `data` as a packet.
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, compute_checksum(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())?;
+        self.last_packet = data.to_string();
+        Ok(())
+    }
+
+    /// Reads a single byte. Returns `None` if the client disconnected.
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Returns whether the client requested to interrupt the execution or
+    /// disconnected, without blocking.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = match self.stream.read(&mut byte) {
+            Ok(0) => Ok(true),
+            Ok(_) => Ok(byte[0] == INTERRUPT),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        };
+        self.stream.set_nonblocking(false)?;
+        result
+    }
+
+    /// Handles a packet and returns the reply. Unsupported packets are replied to
+    /// with an empty packet.
+    fn handle_packet(&mut self, packet: &str) -> io::Result<String> {
+        let mut chars = packet.chars();
+        let command = chars.next().map(String::from).unwrap_or_default();
+        let arguments = chars.as_str();
+        let reply = match command.as_str() {
+            "?" => "S05".to_string(),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(arguments),
+            "p" => self.read_register(arguments),
+            "P" => self.write_register(arguments),
+            "m" => self.read_memory(arguments),
+            "M" => self.write_memory(arguments),
+            "s" => self.resume(true)?,
+            "c" => self.resume(false)?,
+            "Z" | "z" => self.update_point(command == "Z", arguments),
+            "H" => "OK".to_string(),
+            "q" if arguments.starts_with("Supported") => {
+                format!("PacketSize={:x}", 2 * MAX_MEMORY_ACCESS_LEN + 32)
+            }
+            "q" if arguments == "Attached" => "1".to_string(),
+            _ => String::new(),
+        };
+        Ok(reply)
+    }
+
+    /// Returns the value of the register with the given number encoded as bytes.
+    fn register_bytes(&self, number: usize) -> Vec<u8> {
+        let chip8 = &self.chip8;
+        match number {
+            0x0..=0xF => vec![chip8.registers[number]],
+            REGISTER_I => chip8.index.to_be_bytes().to_vec(),
+            REGISTER_PC => chip8.program_counter.to_be_bytes().to_vec(),
+            REGISTER_SP => vec![chip8.stack_pointer],
+            REGISTER_DT => vec![chip8.delay_timer],
+            REGISTER_ST => vec![chip8.sound_timer],
+            _ => unreachable!("Invalid register number"),
+        }
+    }
+
+    /// Sets the register with the given number to the value encoded by `bytes`.
+    /// Returns `false` if the value is invalid for the register.
+    fn set_register_bytes(&mut self, number: usize, bytes: &[u8]) -> bool {
+        if bytes.len() != self.register_bytes(number).len() {
+            return false;
+        }
+        let chip8 = &mut self.chip8;
+        match number {
+            0x0..=0xF => chip8.registers[number] = bytes[0],
+            REGISTER_I => chip8.index = u16::from_be_bytes([bytes[0], bytes[1]]),
+            REGISTER_PC => chip8.program_counter = u16::from_be_bytes([bytes[0], bytes[1]]),
+            REGISTER_SP if bytes[0] as usize <= chip8.stack.len() => chip8.stack_pointer = bytes[0],
+            REGISTER_SP => return false,
+            REGISTER_DT => chip8.delay_timer = bytes[0],
+            REGISTER_ST => chip8.sound_timer = bytes[0],
+            _ => unreachable!("Invalid register number"),
+        }
+        true
+    }
+
+    fn read_registers(&self) -> String {
+        let bytes: Vec<u8> = (0..REGISTER_COUNT)
+            .flat_map(|number| self.register_bytes(number))
+            .collect();
+        encode_hex(&bytes)
+    }
+
+    fn write_registers(&mut self, arguments: &str) -> String {
+        let bytes = match decode_hex(arguments) {
+            Some(bytes) if bytes.len() == self.read_registers().len() / 2 => bytes,
+            _ => return "E01".to_string(),
+        };
+        let mut offset = 0;
+        for number in 0..REGISTER_COUNT {
+            let len = self.register_bytes(number).len();
+            if !self.set_register_bytes(number, &bytes[offset..offset + len]) {
+                return "E01".to_string();
+            }
+            offset += len;
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, arguments: &str) -> String {
+        match usize::from_str_radix(arguments, 16) {
+            Ok(number) if number < REGISTER_COUNT => encode_hex(&self.register_bytes(number)),
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, arguments: &str) -> String {
+        let mut parts = arguments.splitn(2, '=');
+        let number = parts
+            .next()
+            .and_then(|number| usize::from_str_radix(number, 16).ok());
+        let bytes = parts.next().and_then(decode_hex);
+        match (number, bytes) {
+            (Some(number), Some(bytes))
+                if number < REGISTER_COUNT && self.set_register_bytes(number, &bytes) =>
+            {
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// Parses `address,length` and returns the corresponding range of the memory,
+    /// or `None` if the range is invalid.
+    fn memory_range(&self, arguments: &str) -> Option<std::ops::Range<usize>> {
+        let mut parts = arguments.splitn(2, ',');
+        let address = usize::from_str_radix(parts.next()?, 16).ok()?;
+        let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+        let end = address.checked_add(len)?;
+        if len > MAX_MEMORY_ACCESS_LEN || end > self.chip8.memory.len() {
+            return None;
+        }
+        Some(address..end)
+    }
+
+    fn read_memory(&self, arguments: &str) -> String {
+        match self.memory_range(arguments) {
+            Some(range) => encode_hex(&self.chip8.memory[range]),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> String {
+        let mut parts = arguments.splitn(2, ':');
+        let range = parts.next().and_then(|range| self.memory_range(range));
+        let bytes = parts.next().and_then(decode_hex);
+        match (range, bytes) {
+            (Some(range), Some(bytes)) if range.len() == bytes.len() => {
+                self.chip8.memory[range.clone()].copy_from_slice(&bytes);
+                for index in range {
+                    self.chip8.decode_cache.invalidate(index);
+                }
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    /// Inserts or removes a breakpoint or watchpoint given as `type,address,kind`.
+    /// The kind is ignored, and watchpoints cover a single byte.
+    fn update_point(&mut self, insert: bool, arguments: &str) -> String {
+        let mut parts = arguments.split(',');
+        let kind = parts.next();
+        let address = parts
+            .next()
+            .and_then(|address| u16::from_str_radix(address, 16).ok());
+        let address = match address {
+            Some(address) => address,
+            None => return "E01".to_string(),
+        };
+
+        let chip8 = &mut self.chip8;
+        match (kind, insert) {
+            (Some("0"), true) => chip8.add_breakpoint(Breakpoint::Address(address)),
+            (Some("0"), false) => {
+                chip8.remove_breakpoint(Breakpoint::Address(address));
+            }
+            (Some("2"), true) => chip8.add_watchpoint(Watchpoint::Write(address, address)),
+            (Some("2"), false) => {
+                chip8.remove_watchpoint(Watchpoint::Write(address, address));
+            }
+            (Some("3"), true) => chip8.add_watchpoint(Watchpoint::Read(address, address)),
+            (Some("3"), false) => {
+                chip8.remove_watchpoint(Watchpoint::Read(address, address));
+            }
+            _ => return String::new(),
+        }
+        "OK".to_string()
+    }
+
+    /// Resumes the execution until it stops, and returns the corresponding stop
+    /// reply. If `single_step` is set, exactly one instruction is executed.
+    /// Otherwise, the execution continues until a breakpoint or watchpoint is hit,
+    /// the program exits or fails, or the client interrupts the execution.
+    fn resume(&mut self, single_step: bool) -> io::Result<String> {
+        let mut cycles = 0;
+        loop {
+            if self.chip8.has_exited() {
+                return Ok("W00".to_string());
+            }
+            if self.scheduler.cycle(&mut *self.chip8).is_err() {
+                // SIGILL
+                return Ok("S04".to_string());
+            }
+
+            match self.chip8.take_stop_reason() {
+                // a single step executes the instruction even if a breakpoint is set
+                // at its address, which is skipped by the next cycle
+                Some(StopReason::Breakpoint(..)) if single_step => continue,
+                Some(reason) => return Ok(stop_reply(reason)),
+                None => {}
+            }
+
+            if self.chip8.has_exited() {
+                return Ok("W00".to_string());
+            }
+            if single_step {
+                return Ok("S05".to_string());
+            }
+
+            cycles += 1;
+            if cycles % INTERRUPT_CHECK_INTERVAL == 0 && self.interrupted()? {
+                // SIGINT
+                return Ok("S02".to_string());
+            }
+        }
+    }
+}
+
+/// Returns the stop reply for the given stop reason, which reports a SIGTRAP
+/// along with the address of a watchpoint that was hit.
+fn stop_reply(reason: StopReason) -> String {
+    match reason {
+        StopReason::MemoryRead(_, _, address) => format!("T05rwatch:{:x};", address),
+        StopReason::MemoryWrite(_, _, address) => format!("T05watch:{:x};", address),
+        _ => "S05".to_string(),
+    }
+}
+
+/// Computes the checksum of a packet, i.e., the sum of its bytes modulo 256.
+fn compute_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match std::str::from_utf8(pair) {
+            Ok(pair) if pair.len() == 2 => u8::from_str_radix(pair, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Sends `command` as a packet to a GDB stub and returns the reply.
+    fn gdb_command(stream: &mut TcpStream, command: &str) -> String {
+        let checksum = command
+            .bytes()
+            .fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(stream, "${}#{:02x}", command, checksum).expect("Sending packet failed");
+
+        let mut byte = [0];
+        stream.read_exact(&mut byte).expect("Receiving ack failed");
+        assert_eq!(byte[0], b'+');
+        stream
+            .read_exact(&mut byte)
+            .expect("Receiving reply failed");
+        assert_eq!(byte[0], b'$');
+
+        let mut reply = vec![];
+        loop {
+            stream
+                .read_exact(&mut byte)
+                .expect("Receiving reply failed");
+            if byte[0] == b'#' {
+                break;
+            }
+            reply.push(byte[0]);
+        }
+        let mut checksum = [0; 2];
+        stream
+            .read_exact(&mut checksum)
+            .expect("Receiving reply failed");
+        let expected = reply.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        assert_eq!(checksum, format!("{:02x}", expected).as_bytes());
+        stream.write_all(b"+").expect("Sending ack failed");
+        String::from_utf8(reply).expect("Reply is not valid UTF-8")
+    }
+
+    #[test]
+    fn test_gdb_stub() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Binding failed");
+        let address = listener.local_addr().expect("No local address");
+        let server = thread::spawn(move || {
+            let mut chip8 = Chip8::default();
+            // LD VA, 5; LD I, 0x300; LD [I], VA; JP 0x206
+            chip8
+                .load_program(&[0x6A, 0x05, 0xA3, 0x00, 0xFA, 0x55, 0x12, 0x06])
+                .expect("Loading program failed");
+            let (stream, _) = listener.accept().expect("Accepting failed");
+            serve(&mut chip8, stream).expect("Serving failed");
+            chip8
+        });
+
+        let mut stream = TcpStream::connect(address).expect("Connecting failed");
+
+        // packets with invalid checksums are rejected
+        stream.write_all(b"$g#00").expect("Sending packet failed");
+        let mut byte = [0];
+        stream.read_exact(&mut byte).expect("Receiving nack failed");
+        assert_eq!(byte[0], b'-');
+
+        assert_eq!(gdb_command(&mut stream, "?"), "S05");
+        assert_eq!(gdb_command(&mut stream, "vMustReplyEmpty"), "");
+        assert_eq!(
+            gdb_command(&mut stream, "g"),
+            format!("{}0000020000{}", "00".repeat(16), "00".repeat(2))
+        );
+
+        assert_eq!(gdb_command(&mut stream, "s"), "S05");
+        assert_eq!(gdb_command(&mut stream, "pa"), "05");
+        assert_eq!(gdb_command(&mut stream, "p11"), "0202");
+        assert_eq!(gdb_command(&mut stream, "p15"), "E01");
+
+        assert_eq!(gdb_command(&mut stream, "Z0,204,2"), "OK");
+        assert_eq!(gdb_command(&mut stream, "c"), "S05");
+        assert_eq!(gdb_command(&mut stream, "p11"), "0204");
+        assert_eq!(gdb_command(&mut stream, "p10"), "0300");
+        assert_eq!(gdb_command(&mut stream, "z0,204,2"), "OK");
+
+        assert_eq!(gdb_command(&mut stream, "Z2,305,1"), "OK");
+        assert_eq!(gdb_command(&mut stream, "c"), "T05watch:305;");
+        assert_eq!(gdb_command(&mut stream, "p11"), "0206");
+        assert_eq!(gdb_command(&mut stream, "m308,3"), "000005");
+        assert_eq!(gdb_command(&mut stream, "z2,305,1"), "OK");
+
+        assert_eq!(gdb_command(&mut stream, "M400,2:abcd"), "OK");
+        assert_eq!(gdb_command(&mut stream, "m400,2"), "abcd");
+        assert_eq!(gdb_command(&mut stream, "mfff,2"), "E01");
+        assert_eq!(gdb_command(&mut stream, "P0=7f"), "OK");
+        assert_eq!(gdb_command(&mut stream, "P12=11"), "E01");
+
+        // the program loops until interrupted
+        stream
+            .write_all(b"$c#63\x03")
+            .expect("Sending packet failed");
+        stream.read_exact(&mut byte).expect("Receiving ack failed");
+        assert_eq!(byte[0], b'+');
+        let mut reply = [0; 7];
+        stream
+            .read_exact(&mut reply)
+            .expect("Receiving reply failed");
+        assert_eq!(&reply, b"$S02#b5");
+        stream.write_all(b"+").expect("Sending ack failed");
+
+        assert_eq!(gdb_command(&mut stream, "D"), "OK");
+        let chip8 = server.join().expect("Server failed");
+        assert_eq!(chip8.registers[0], 0x7F);
+        assert_eq!(chip8.memory[0x400..0x402], [0xAB, 0xCD]);
+        assert_eq!(chip8.program_counter, 0x206);
+    }
+}
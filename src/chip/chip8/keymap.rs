@@ -0,0 +1,374 @@
+/// The number of keys of the keypad, each of which is wired to an input pin.
+const KEYPAD_SIZE: usize = 16;
+
+/// The built-in layouts. Each layout lists the keys mapped to the pins `0x0` to
+/// `0xF`. The keyboard layouts map the 4x4 block of keys below the number row
+/// and the number row itself to the keypad, preserving the positions of the keys
+/// (`1 2 3 C`, `4 5 6 D`, `7 8 9 E`, `A 0 B F`). Keys are given by the characters
+/// they produce without Shift, e.g., `& é " '` for the number row of AZERTY. The
+/// numeric keypad layout maps the digits to the pins of the same value, and
+/// `/ * - + . =` to `0xA` - `0xF`.
+const LAYOUTS: [(&str, [char; KEYPAD_SIZE]); 4] = [
+    (
+        "qwerty",
+        [
+            'x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+        ],
+    ),
+    (
+        "azerty",
+        [
+            'x', '&', 'é', '"', 'a', 'z', 'e', 'q', 's', 'd', 'w', 'c', '\'', 'r', 'f', 'v',
+        ],
+    ),
+    (
+        "dvorak",
+        [
+            'q', '1', '2', '3', '\'', ',', '.', 'a', 'o', 'e', ';', 'j', '4', 'p', 'u', 'k',
+        ],
+    ),
+    (
+        "numpad",
+        [
+            '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '/', '*', '-', '+', '.', '=',
+        ],
+    ),
+];
+
+/// The name of the section holding the keymap used for all programs without an
+/// override.
+const DEFAULT_SECTION: &str = "default";
+
+/// Error type for errors that occur during parsing a keymap configuration. The
+/// line numbers start at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeymapError {
+    /// The line passed in the enum value is malformed.
+    InvalidLine(usize),
+    /// The layout passed in the enum value is not a built-in layout.
+    UnknownLayout(usize, String),
+    /// The keymap of the section passed in the enum value does not map a key to
+    /// the given pin.
+    MissingPin(String, u8),
+    /// The keymap of the section passed in the enum value maps the given key to
+    /// several pins.
+    DuplicateKey(String, char),
+}
+
+/// Maps the keys of a keyboard to the keys of the keypad, i.e., to the input
+/// pins. Every pin has exactly one key, and keys are lowercase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [char; KEYPAD_SIZE],
+}
+
+/// A keymap configuration, consisting of a default keymap and keymaps overriding
+/// it for programs with the given file names.
+///
+/// The configuration uses a subset of TOML. The section `[default]` holds the
+/// default keymap, and sections named after the file name of a program (e.g.,
+/// `["pong.ch8"]`) hold overrides. Each section may start from a built-in layout
+/// (e.g., `layout = "azerty"`) and assigns keys to pins (e.g., `0x5 = "w"`).
+/// Sections without a layout have to assign keys to all pins. Lines before the
+/// first section belong to the default keymap, and `#` starts a comment.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeymapConfig {
+    default: Keymap,
+    overrides: Vec<(String, Keymap)>,
+}
+
+impl Keymap {
+    /// Returns the built-in layout called `name`, which is one of `qwerty`,
+    /// `azerty`, `dvorak` and `numpad`.
+    pub fn layout(name: &str) -> Option<Keymap> {
+        LAYOUTS
+            .iter()
+            .find(|(layout, _)| layout.eq_ignore_ascii_case(name))
+            .map(|(_, keys)| Keymap { keys: *keys })
+    }
+
+    /// Returns the names of the built-in layouts.
+    pub fn layout_names() -> Vec<&'static str> {
+        LAYOUTS.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Returns the pin `key` is mapped to, if any. Keys are case-insensitive.
+    pub fn pin(&self, key: char) -> Option<u8> {
+        let key = key.to_ascii_lowercase();
+        self.keys
+            .iter()
+            .position(|mapped| *mapped == key)
+            .map(|pin| pin as u8)
+    }
+
+    /// Returns the key mapped to `pin`.
+    ///
+    /// # Panics
+    /// In case `pin` is larger than `0xF`.
+    pub fn key(&self, pin: u8) -> char {
+        self.keys[pin as usize]
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::layout("qwerty").unwrap()
+    }
+}
+
+impl KeymapConfig {
+    /// Parses a keymap configuration. Returns an error if a line is malformed,
+    /// or if a keymap does not map exactly one key to each pin.
+    pub fn parse(source: &str) -> Result<KeymapConfig, KeymapError> {
+        let mut sections: Vec<(String, Vec<Option<char>>)> =
+            vec![(DEFAULT_SECTION.to_string(), vec![None; KEYPAD_SIZE])];
+        for (number, line) in source.lines().enumerate() {
+            let number = number + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = unquote(line[1..line.len() - 1].trim())
+                    .ok_or(KeymapError::InvalidLine(number))?;
+                // an explicit default section replaces the implicit one if it is empty
+                let implicit_default_empty =
+                    sections.len() == 1 && sections[0].1.iter().all(Option::is_none);
+                if name == DEFAULT_SECTION && implicit_default_empty {
+                    sections.clear();
+                }
+                if sections.iter().any(|(section, _)| *section == name) {
+                    return Err(KeymapError::InvalidLine(number));
+                }
+                sections.push((name, vec![None; KEYPAD_SIZE]));
+                continue;
+            }
+
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => return Err(KeymapError::InvalidLine(number)),
+            };
+            let value = unquote(value).ok_or(KeymapError::InvalidLine(number))?;
+            let keys = &mut sections.last_mut().unwrap().1;
+            if key == "layout" {
+                let layout = Keymap::layout(&value)
+                    .ok_or_else(|| KeymapError::UnknownLayout(number, value.clone()))?;
+                // keys assigned before the layout take precedence
+                for (key, layout_key) in keys.iter_mut().zip(layout.keys.iter()) {
+                    key.get_or_insert(*layout_key);
+                }
+                continue;
+            }
+
+            let pin = match u8::from_str_radix(key.trim_start_matches("0x"), 16) {
+                Ok(pin) if (pin as usize) < KEYPAD_SIZE => pin,
+                _ => return Err(KeymapError::InvalidLine(number)),
+            };
+            let mut chars = value.chars();
+            keys[pin as usize] = match (chars.next(), chars.next()) {
+                (Some(key), None) => Some(key.to_ascii_lowercase()),
+                _ => return Err(KeymapError::InvalidLine(number)),
+            };
+        }
+
+        let mut default = Keymap::default();
+        let mut overrides: Vec<(String, Keymap)> = vec![];
+        for (name, keys) in sections {
+            // sections without any assignment (e.g., an empty default section)
+            // keep the default keymap
+            if name == DEFAULT_SECTION && keys.iter().all(Option::is_none) {
+                continue;
+            }
+            let keymap = validate(&name, &keys)?;
+            if name == DEFAULT_SECTION {
+                default = keymap;
+            } else {
+                overrides.push((name, keymap));
+            }
+        }
+        Ok(KeymapConfig { default, overrides })
+    }
+
+    /// Returns the keymap for the program at `program_path`, which is the override
+    /// for the file name of the program if there is one, and the default keymap
+    /// otherwise.
+    pub fn keymap_for(&self, program_path: &str) -> Keymap {
+        let file_name = std::path::Path::new(program_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(program_path);
+        self.overrides
+            .iter()
+            .find(|(name, _)| name == file_name)
+            .map_or(self.default, |(_, keymap)| *keymap)
+    }
+}
+
+/// Checks that `keys` map exactly one key to each pin, and builds the keymap of
+/// the section called `name` from them.
+fn validate(name: &str, keys: &[Option<char>]) -> Result<Keymap, KeymapError> {
+    let mut keymap = Keymap {
+        keys: [' '; KEYPAD_SIZE],
+    };
+    for (pin, key) in keys.iter().enumerate() {
+        let key = key.ok_or_else(|| KeymapError::MissingPin(name.to_string(), pin as u8))?;
+        if keymap.keys[..pin].contains(&key) {
+            return Err(KeymapError::DuplicateKey(name.to_string(), key));
+        }
+        keymap.keys[pin] = key;
+    }
+    Ok(keymap)
+}
+
+/// Removes a comment starting with `#` outside of quotes from `line`.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Removes the double quotes around `value`. Values without quotes are only
+/// accepted if they consist of letters, digits, `_` and `-`, like TOML bare keys.
+fn unquote(value: &str) -> Option<String> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        let inner = &value[1..value.len() - 1];
+        return Some(inner.replace("\\\"", "\"").replace("\\\\", "\\"));
+    }
+    let bare = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        Some(value.to_string())
+    } else {
+        None
+    }
+}
+
+impl std::fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeymapError::InvalidLine(line) => write!(f, "Invalid keymap in line {}.", line),
+            KeymapError::UnknownLayout(line, layout) => write!(
+                f,
+                "Unknown layout {:?} in line {}. Available layouts are {}.",
+                layout,
+                line,
+                Keymap::layout_names().join(", ")
+            ),
+            KeymapError::MissingPin(section, pin) => write!(
+                f,
+                "The keymap {:?} does not map a key to pin {:#X}.",
+                section, pin
+            ),
+            KeymapError::DuplicateKey(section, key) => write!(
+                f,
+                "The keymap {:?} maps the key {:?} to several pins.",
+                section, key
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keymap_layouts() {
+        for name in Keymap::layout_names() {
+            let keymap = Keymap::layout(name).expect("Unknown layout");
+            for pin in 0x0..=0xF {
+                assert_eq!(keymap.pin(keymap.key(pin)), Some(pin));
+            }
+        }
+
+        let qwerty = Keymap::default();
+        assert_eq!(qwerty.pin('w'), Some(0x5));
+        assert_eq!(qwerty.pin('W'), Some(0x5));
+        assert_eq!(qwerty.pin('x'), Some(0x0));
+        assert_eq!(qwerty.pin('p'), None);
+        let azerty = Keymap::layout("AZERTY").expect("Unknown layout");
+        assert_eq!(azerty.key(0x5), 'z');
+        for (key, pin) in &[('&', 0x1), ('é', 0x2), ('"', 0x3), ('\'', 0xC)] {
+            assert_eq!(azerty.pin(*key), Some(*pin));
+        }
+        assert_eq!(azerty.pin('1'), None);
+        assert_eq!(
+            Keymap::layout("numpad").map(|keymap| keymap.pin('7')),
+            Some(Some(0x7))
+        );
+        assert_eq!(Keymap::layout("colemak"), None);
+    }
+
+    #[test]
+    fn test_keymap_config() {
+        let config = KeymapConfig::parse(
+            r##"
+            # AZERTY by default
+            layout = "azerty"
+
+            ["pong.ch8"]
+            layout = "qwerty"
+            0x1 = "u"   # left paddle
+            0x4 = "j"
+            0xC = "#"
+            "##,
+        )
+        .expect("Parsing keymap failed");
+        assert_eq!(
+            config.keymap_for("games/tetris.ch8"),
+            Keymap::layout("azerty").unwrap()
+        );
+        let pong = config.keymap_for("games/pong.ch8");
+        assert_eq!(pong.pin('u'), Some(0x1));
+        assert_eq!(pong.pin('j'), Some(0x4));
+        assert_eq!(pong.pin('#'), Some(0xC));
+        assert_eq!(pong.pin('1'), None);
+        assert_eq!(pong.pin('w'), Some(0x5));
+
+        // an explicit default section without a layout has to cover all pins
+        let mut complete = String::from("[default]\n");
+        for pin in 0x0..=0xF {
+            complete += &format!("{:X} = \"{:x}\"\n", pin, pin);
+        }
+        let config = KeymapConfig::parse(&complete).expect("Parsing keymap failed");
+        assert_eq!(config.keymap_for("pong.ch8").pin('a'), Some(0xA));
+        assert_eq!(KeymapConfig::parse(""), Ok(KeymapConfig::default()));
+    }
+
+    #[test]
+    fn test_invalid_keymap_config() {
+        assert_eq!(
+            KeymapConfig::parse("[default]\n0x0 = \"x\""),
+            Err(KeymapError::MissingPin("default".to_string(), 0x1))
+        );
+        assert_eq!(
+            KeymapConfig::parse("layout = \"qwerty\"\n0x0 = \"w\""),
+            Err(KeymapError::DuplicateKey("default".to_string(), 'w'))
+        );
+        assert_eq!(
+            KeymapConfig::parse("\nlayout = \"colemak\""),
+            Err(KeymapError::UnknownLayout(2, "colemak".to_string()))
+        );
+        assert_eq!(
+            KeymapConfig::parse("layout = \"qwerty\"\n0x10 = \"p\""),
+            Err(KeymapError::InvalidLine(2))
+        );
+        assert_eq!(
+            KeymapConfig::parse("0x1 = \"ab\""),
+            Err(KeymapError::InvalidLine(1))
+        );
+        assert_eq!(
+            KeymapConfig::parse("[a]\nlayout = \"qwerty\"\n[a]"),
+            Err(KeymapError::InvalidLine(3))
+        );
+    }
+}
@@ -1,28 +1,64 @@
+/// Assembly of programs.
+pub mod assembler;
 /// CHIP-8 constants.
 mod constants;
+/// Cursive debugger view.
+pub mod cursive_debugger;
 /// Cursive display output.
 pub mod cursive_display;
+/// Breakpoints and watchpoints.
+pub mod debugger;
+/// Caching of decoded instructions.
+mod decode_cache;
+/// Disassembly of programs.
+pub mod disassembler;
+/// GDB remote serial protocol server.
+pub mod gdb_stub;
+/// Mapping keyboard keys to the keys of the keypad.
+pub mod keymap;
 /// Decoding of opcodes and their execution.
 mod opcodes;
+/// Configuration of ambiguous instructions.
+pub mod quirks;
+/// Sources of random numbers.
+pub mod random;
+/// Serialization of the machine state.
+mod save_state;
 /// Convenience functions for modification of the CHIP-8 state.
 mod util;
+/// The supported variants of the machine.
+pub mod variant;
 
 #[cfg(test)]
 mod tests;
 
 use crate::chip::{
+    audio::{AudioSink, Tone},
     chip8::constants::{
-        CHIP8_CHARSET, CHIP8_CHARSET_LEN, CHIP8_CHARSET_OFFSET, CHIP8_MAX_PROGRAM_SIZE,
-        CHIP8_TIMER_RESOLUTION,
+        CHIP8_BEEP_PATTERN, CHIP8_CHARSET, CHIP8_CHARSET_LEN, CHIP8_CHARSET_OFFSET,
+        CHIP8_LORES_HEIGHT, CHIP8_LORES_WIDTH, CHIP8_PROGRAM_OFFSET, SCHIP_BIG_CHARSET,
+        SCHIP_BIG_CHARSET_LEN, SCHIP_BIG_CHARSET_OFFSET, SCHIP_HIRES_HEIGHT, SCHIP_HIRES_WIDTH,
+        SCHIP_RPL_FLAGS, XO_CHIP_AUDIO_PATTERN_LEN, XO_CHIP_DEFAULT_PITCH, XO_CHIP_PLANES,
     },
-    chip8::opcodes::Opcode,
-    Chip, LoadProgramError,
+    chip8::{
+        debugger::{Debugger, StopReason},
+        decode_cache::DecodeCache,
+        opcodes::{DecodedInstruction, Opcode},
+        quirks::Quirks,
+        random::{RandomSource, SplitMix64},
+        variant::Variant,
+    },
+    framebuffer::{Framebuffer, Rect},
+    Chip, ExecutionError, LoadProgramError, LoadStateError,
 };
 
+use rand::{thread_rng, Rng};
+
 /// Represents the state of the CHIP-8.
 pub struct Chip8 {
-    /// 4096 bytes of main memory
-    memory: [u8; 4096],
+    /// The main memory. It holds 4096 bytes for the CHIP-8 variant and 65536
+    /// bytes for the XO-CHIP variant.
+    memory: Vec<u8>,
 
     /// 16 registers where each can store one byte
     registers: [u8; 16],
@@ -36,15 +72,27 @@ pub struct Chip8 {
     /// The output pins. Note that those are usually directly wired
     /// up to the pixels of the display. However, given that this implementation
     /// considers a display as optional, we refer to them as output_pins for
-    /// the sake of generality.
-    output_pins: [bool; 64 * 32],
-
-    /// The delay timer. Note that this timer is decremented every
-    /// `CHIP8_TIMER_RESOLUTION` cycles.
+    /// the sake of generality. There is one set of output pins per bitplane.
+    /// Each row of pins is packed into a u128, where the pin in column `x` is
+    /// bit `127 - x` (see `util::pixel_mask`). Only the first `width` columns and
+    /// `height` rows of the current resolution are in use, and pins outside of
+    /// them are always cleared.
+    output_pins: [[u128; SCHIP_HIRES_HEIGHT]; XO_CHIP_PLANES],
+
+    /// A bitmask of the bitplanes affected by drawing, clearing and scrolling.
+    /// Bit `i` corresponds to bitplane `i`.
+    selected_planes: u8,
+
+    /// A flag that indicates whether the SUPER-CHIP high resolution mode
+    /// is active.
+    hires: bool,
+
+    /// The delay timer. Note that this timer is decremented by `tick_timers`,
+    /// which is supposed to be called at 60 Hz.
     delay_timer: u8,
 
-    /// The sound timer. Note that this timer is decremented every
-    /// `CHIP8_TIMER_RESOLUTION` cycles.
+    /// The sound timer. Note that this timer is decremented by `tick_timers`,
+    /// which is supposed to be called at 60 Hz.
     sound_timer: u8,
 
     /// The input pins. Note that those input pins are usually directly wired
@@ -60,12 +108,45 @@ pub struct Chip8 {
     /// A pointer, pointing to the current position in the stack.
     stack_pointer: u8,
 
-    /// A helper variable to properly implement the timer resolution.
-    cycles_since_timer_dec: u8,
+    /// The rectangles of output pins that changed since the output pins were
+    /// last marked as displayed (see `util::mark_dirty`).
+    dirty_rects: Vec<Rect>,
+
+    /// The SUPER-CHIP RPL user flags, which can be written and read using
+    /// `0xFX75` and `0xFX85`.
+    rpl_flags: [u8; SCHIP_RPL_FLAGS],
+
+    /// A flag that indicates whether the program exited via `0x00FD`.
+    exited: bool,
+
+    /// The XO-CHIP audio pattern buffer, which holds a 1-bit waveform that is
+    /// played while the sound timer is active.
+    audio_pattern: [u8; XO_CHIP_AUDIO_PATTERN_LEN],
+
+    /// The XO-CHIP pitch register, which determines the playback rate of the
+    /// audio pattern buffer.
+    pitch: u8,
+
+    /// The variant of the machine.
+    variant: Variant,
+
+    /// The quirks determining the behaviour of ambiguous instructions.
+    quirks: Quirks,
+
+    /// The source of random numbers used by the RND instruction.
+    random: Box<dyn RandomSource>,
 
-    /// A flag that indicates whether the output pins changed since it
-    /// was last set to false.
-    draw: bool,
+    /// The sink playing the sound, if any.
+    audio_sink: Option<Box<dyn AudioSink>>,
+
+    /// The breakpoints and watchpoints.
+    debugger: Debugger,
+
+    /// The instructions decoded so far, indexed by the address they start at, so
+    /// that loops are not decoded over and over again. An entry is cleared as soon
+    /// as any byte of its opcode is written, hence self-modifying programs execute
+    /// the modified instructions.
+    decode_cache: DecodeCache,
 }
 
 impl Chip for Chip8 {
@@ -74,46 +155,95 @@ impl Chip for Chip8 {
     /// range, because it is more convenient to handle.
     type PinAddress = u8;
 
-    /// A CHIP-8 memory address is in the range between 0 and 4096 (exclusive). We
+    /// A CHIP-8 memory address is in the range between 0 and 4096 (exclusive), and
+    /// an XO-CHIP memory address is in the range between 0 and 65536 (exclusive). We
     /// represent it using a u16. This means we have to assert that it is
     /// in the right range whenever we set it.
     type MemoryAddress = u16;
 
+    /// The CHIP-8 stops at breakpoints and watchpoints.
+    type StopReason = StopReason;
+
     fn load_program(&mut self, program: &[u8]) -> Result<(), LoadProgramError> {
-        if program.len() > (CHIP8_MAX_PROGRAM_SIZE as usize) {
-            return Err(LoadProgramError::ProgramTooLarge(program.len()));
+        let max_program_size = self.variant.max_program_size();
+        if program.len() > max_program_size {
+            return Err(LoadProgramError::ProgramTooLarge(
+                program.len(),
+                max_program_size,
+            ));
         }
 
         for i in 0..program.len() {
-            self.set_memory_byte(program[i], (0x200 + i) as u16);
+            self.set_memory_byte(program[i], CHIP8_PROGRAM_OFFSET + i as u16);
         }
 
         Ok(())
     }
 
-    fn cycle(&mut self) {
-        let opcode = self.next_instruction();
-        let mut state = self;
-        opcode.execute(&mut state);
+    fn cycle(&mut self) -> Result<(), ExecutionError> {
+        if self.exited {
+            return Ok(());
+        }
+
+        let instruction = self.next_instruction()?;
+        let address = self.program_counter as usize;
+        let word = (self.memory[address] as u16) << 8 | self.memory[address + 1] as u16;
+        if self.debugger.stops_before(self.program_counter, word) {
+            return Ok(());
+        }
 
-        state.cycles_since_timer_dec += 1;
+        let registers = self.registers;
+        instruction.execute(self)?;
+        self.debugger.on_registers(&registers, &self.registers);
 
-        if state.cycles_since_timer_dec % CHIP8_TIMER_RESOLUTION == 0 {
-            if state.delay_timer > 0 {
-                state.delay_timer -= 1;
-            }
+        Ok(())
+    }
 
-            if state.sound_timer > 0 {
-                // TODO let it beep
-                state.sound_timer -= 1;
-            }
+    fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        // the sound plays for as many frames as the sound timer is active
+        if let Some(audio_sink) = &mut self.audio_sink {
+            let tone = if self.sound_timer > 0 {
+                Some(Tone {
+                    pattern: if self.audio_pattern == [0; XO_CHIP_AUDIO_PATTERN_LEN] {
+                        CHIP8_BEEP_PATTERN
+                    } else {
+                        self.audio_pattern
+                    },
+                    rate: 4000.0 * 2_f64.powf((self.pitch as f64 - 64.0) / 48.0),
+                })
+            } else {
+                None
+            };
+            audio_sink.play_frame(tone.as_ref());
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
 
-            state.cycles_since_timer_dec = 0;
+    fn framebuffer(&self) -> Framebuffer {
+        let (width, height) = self.resolution();
+        let planes = self.variant.planes();
+        let mut framebuffer = Framebuffer::new(width, height, planes);
+        for y in 0..height {
+            for x in 0..width {
+                let value = (0..planes)
+                    .filter(|plane| self.output_pins[*plane][y] & util::pixel_mask(x) != 0)
+                    .map(|plane| 1 << plane)
+                    .sum();
+                framebuffer.set_pixel(x, y, value);
+            }
         }
+        framebuffer.with_dirty_rects(self.dirty_rects.clone())
     }
 
-    fn read_output_pins(&self) -> &[bool] {
-        &self.output_pins
+    fn clear_dirty_rects(&mut self) {
+        self.dirty_rects.clear();
     }
 
     fn set_input_pin(&mut self, pin: u8, value: bool) {
@@ -126,47 +256,213 @@ impl Chip for Chip8 {
             self.input_pins[i] = false;
         }
     }
+
+    fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    fn call_depth(&self) -> usize {
+        self.stack_pointer as usize
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        save_state::save_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), LoadStateError> {
+        let (mut restored, random_state) = save_state::load_state(state)?;
+        // keep the random source, which may have been replaced by the user
+        self.random.load_state(random_state)?;
+        std::mem::swap(&mut restored.random, &mut self.random);
+        restored.audio_sink = self.audio_sink.take();
+        restored.debugger = Debugger::take_points(&mut self.debugger);
+        *self = restored;
+        Ok(())
+    }
+
+    fn take_stop_reason(&mut self) -> Option<StopReason> {
+        self.debugger.take_stop_reason()
+    }
 }
 
 impl Chip8 {
     /// Constructs a new CHIP-8 and appropriately initializes all fields so that
     /// it is ready for the first execution cycle. Essentially this means that
     /// the program counter is set to 0x200 and the default CHIP-8 charset is
-    /// loaded at memory address `CHIP8_CHARSET_OFFSET`. Note that no program is
-    /// loaded upon initialization.
-    pub fn new() -> Self {
-        let mut memory = [0; 4096];
+    /// loaded at memory address `CHIP8_CHARSET_OFFSET`, followed by the SUPER-CHIP
+    /// big charset at `SCHIP_BIG_CHARSET_OFFSET`. Note that no program is
+    /// loaded upon initialization. The given `quirks` determine the behaviour of
+    /// ambiguous instructions.
+    pub fn new(quirks: Quirks) -> Self {
+        Chip8::with_variant(Variant::Chip8, quirks)
+    }
+
+    /// Constructs a new machine of the given `variant`. Apart from the variant, it
+    /// is initialized in the same way as by `Chip8::new`. The random number
+    /// generator is seeded randomly.
+    pub fn with_variant(variant: Variant, quirks: Quirks) -> Self {
+        Chip8::with_seed(variant, quirks, thread_rng().gen())
+    }
+
+    /// Constructs a new machine of the given `variant`, whose random number
+    /// generator is seeded with `seed`. Machines constructed with the same seed
+    /// execute programs identically, given the same inputs.
+    pub fn with_seed(variant: Variant, quirks: Quirks, seed: u64) -> Self {
+        let mut memory = vec![0; variant.memory_size()];
         for i in 0..CHIP8_CHARSET_LEN {
             memory[(i + CHIP8_CHARSET_OFFSET) as usize] = CHIP8_CHARSET[i as usize];
         }
+        for i in 0..SCHIP_BIG_CHARSET_LEN {
+            memory[(i + SCHIP_BIG_CHARSET_OFFSET) as usize] = SCHIP_BIG_CHARSET[i as usize];
+        }
 
         Chip8 {
             memory,
             registers: [0; 16],
             index: 0,
             program_counter: 0x200,
-            output_pins: [false; 64 * 32],
+            output_pins: [[0; SCHIP_HIRES_HEIGHT]; XO_CHIP_PLANES],
+            selected_planes: 0x1,
+            hires: false,
             delay_timer: 0,
             sound_timer: 0,
             stack: [0; 16],
             stack_pointer: 0,
             input_pins: [false; 16],
-            draw: false,
-            cycles_since_timer_dec: 0,
+            dirty_rects: vec![],
+            rpl_flags: [0; SCHIP_RPL_FLAGS],
+            exited: false,
+            audio_pattern: [0; XO_CHIP_AUDIO_PATTERN_LEN],
+            pitch: XO_CHIP_DEFAULT_PITCH,
+            variant,
+            quirks,
+            random: Box::new(SplitMix64::new(seed)),
+            audio_sink: None,
+            debugger: Debugger::default(),
+            decode_cache: DecodeCache::new(variant.memory_size()),
+        }
+    }
+
+    /// Replaces the source of random numbers used by the RND instruction.
+    pub fn set_random_source(&mut self, random: Box<dyn RandomSource>) {
+        self.random = random;
+    }
+
+    /// Sets the sink playing the sound. It is driven whenever the timers are
+    /// ticked, and plays the audio pattern while the sound timer is active. If
+    /// the program did not load an audio pattern, a beep is played instead.
+    pub fn set_audio_sink(&mut self, audio_sink: Box<dyn AudioSink>) {
+        self.audio_sink = Some(audio_sink);
+    }
+
+    /// Removes the sink playing the sound and returns it, if any.
+    pub fn take_audio_sink(&mut self) -> Option<Box<dyn AudioSink>> {
+        self.audio_sink.take()
+    }
+
+    /// Returns the current resolution of the display as `(width, height)`. This is
+    /// `(64, 32)` in low resolution mode and `(128, 64)` in SUPER-CHIP high
+    /// resolution mode.
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            (SCHIP_HIRES_WIDTH, SCHIP_HIRES_HEIGHT)
+        } else {
+            (CHIP8_LORES_WIDTH, CHIP8_LORES_HEIGHT)
         }
     }
 
-    /// Fetches the next instruction based on the current state of self.program_counter.
+    /// Returns the variant of the machine.
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Returns the registers `V0` to `VF`.
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Returns the index register.
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Returns the program counter.
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// Returns the return addresses on the stack, from the bottom to the top.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    /// Returns the delay timer and the sound timer as `(delay, sound)`.
+    pub fn timers(&self) -> (u8, u8) {
+        (self.delay_timer, self.sound_timer)
+    }
+
+    /// Returns the main memory.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Returns whether the output pin in column `x` and row `y` of the given
+    /// bitplane is set.
     ///
     /// # Panics
-    /// In case `self.program_counter` points to an address which would lead to loading
-    /// bytes from invalid memory addresses.
-    fn next_instruction(&self) -> Opcode {
-        assert!(self.program_counter <= 4094);
-        Opcode::new(&[
-            self.memory[self.program_counter as usize],
-            self.memory[(self.program_counter + 1) as usize],
-        ])
+    /// In case `plane` does not refer to a bitplane of the machine's variant, or
+    /// in case `x` or `y` are outside of the current resolution.
+    pub fn output_pin(&self, plane: usize, x: usize, y: usize) -> bool {
+        assert!(plane < self.variant.planes());
+        let (width, height) = self.resolution();
+        assert!(x < width && y < height);
+        self.output_pins[plane][y] & util::pixel_mask(x) != 0
+    }
+
+    /// Fetches and decodes the next instruction based on the current state of
+    /// self.program_counter, unless it is already in the decode cache. Returns an
+    /// error in case `self.program_counter` points to an address which would lead to
+    /// loading bytes from invalid memory addresses, or to an invalid opcode. For the XO-CHIP variant, this also
+    /// fetches the second half of four byte instructions.
+    fn next_instruction(&mut self) -> Result<DecodedInstruction, ExecutionError> {
+        let address = self.program_counter as usize;
+        if let Some(instruction) = self.decode_cache.get(address) {
+            return Ok(instruction);
+        }
+
+        let opcode = self.fetch_opcode()?;
+        let instruction = opcode.decode().ok_or(ExecutionError::UnknownOpcode(
+            self.program_counter,
+            [self.memory[address], self.memory[address + 1]],
+        ))?;
+        self.decode_cache.insert(address, instruction);
+        Ok(instruction)
+    }
+
+    /// Fetches the opcode at self.program_counter, including the second half of four
+    /// byte XO-CHIP opcodes. Returns an error in case an opcode would be loaded from
+    /// invalid memory addresses.
+    fn fetch_opcode(&self) -> Result<Opcode, ExecutionError> {
+        let address = self.program_counter as usize;
+        if address + 1 >= self.memory.len() {
+            return Err(ExecutionError::ProgramCounterOutOfBounds(
+                self.program_counter,
+            ));
+        }
+        let opcode = Opcode::new(&[self.memory[address], self.memory[address + 1]]);
+
+        if self.variant == Variant::XoChip && opcode.has_long_operand() {
+            if address + 3 >= self.memory.len() {
+                return Err(ExecutionError::ProgramCounterOutOfBounds(
+                    self.program_counter,
+                ));
+            }
+            return Ok(
+                opcode.with_long_operand(&[self.memory[address + 2], self.memory[address + 3]])
+            );
+        }
+
+        Ok(opcode)
     }
 
     /// Sets a memory byte
@@ -174,13 +470,20 @@ impl Chip8 {
     /// # Panics
     /// In case the supplied memory address would result in a buffer overflow.
     fn set_memory_byte(&mut self, byte: u8, index: u16) {
-        assert!(index < 4096);
+        assert!((index as usize) < self.memory.len());
         self.memory[index as usize] = byte;
+        self.decode_cache.invalidate(index as usize);
+    }
+
+    /// Maps the given address to the corresponding index of the memory, wrapping
+    /// around at the end of the memory.
+    fn memory_index(&self, address: u16) -> usize {
+        address as usize % self.memory.len()
     }
 }
 
 impl Default for Chip8 {
     fn default() -> Self {
-        Chip8::new()
+        Chip8::new(Quirks::default())
     }
 }
@@ -1,32 +1,42 @@
-use core::convert::TryFrom;
-use rand::{thread_rng, Rng};
-use std::marker::PhantomData;
-
 use crate::chip::chip8::{
-    constants::CHIP8_CHARSET_OFFSET,
+    constants::{
+        CHIP8_CHARSET_OFFSET, SCHIP_BIG_CHARSET_OFFSET, XO_CHIP_AUDIO_PATTERN_LEN, XO_CHIP_PLANES,
+    },
     opcodes::{
-        Instruction, InstructionParsingError, InstructionWithAddress, InstructionWithOperands,
-        InstructionWithRegAndValue, Opcode,
+        Instruction, InstructionWithAddress, InstructionWithLongAddress, InstructionWithOperands,
+        InstructionWithRegAndValue,
     },
+    quirks::IndexIncrement,
     util, Chip8,
 };
+use crate::chip::{framebuffer::Rect, ExecutionError};
 
 define_instruction_with_reg_and_value!(Ldr, LdrInstruction, 0x6);
 impl Instruction for LdrInstruction {
     /// Opcode of the form `0x6XYZ` (LDR). Load a value `YZ` into `state.registers[X]`.
-    fn execute(&self, mut state: &mut Chip8) {
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
         state.registers[self.reg as usize] = self.value;
         util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!("LD V{:X}, {:#04X}", self.reg, self.value))
     }
 }
 
 define_instruction_with_reg_and_value!(Add, AddInstruction, 0x7);
 impl Instruction for AddInstruction {
     /// Opcode of the form `0x7XYZ` (ADD). Add value `YZ` into `state.registers[X]`.    
-    fn execute(&self, mut state: &mut Chip8) {
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
         state.registers[self.reg as usize] =
             state.registers[self.reg as usize].wrapping_add(self.value);
         util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!("ADD V{:X}, {:#04X}", self.reg, self.value))
     }
 }
 
@@ -43,6 +53,9 @@ impl Instruction for RegInstruction {
     ///
     /// - If `Z == 0x3`, it sets `state.registers[X] = state.registers[X] ^ state.registers[Y]`
     ///
+    ///   For `Z` in `0x1` to `0x3`, `state.registers[0xF]` is set to `0` if the
+    ///   `logic_resets_vf` quirk is set.
+    ///
     /// - If `Z == 0x4`, it sets `state.registers[X] = state.registers[X] + state.registers[Y]`;
     ///   `state.registers[0xF]` is set to `1` if there is an overflow, and to `0` otherwise.
     ///
@@ -58,7 +71,10 @@ impl Instruction for RegInstruction {
     /// - If `Z == 0xE`, it sets `state.registers[X] = state.registers[X] << 1`; `state.registers[0xF]`
     ///   is set to `1` if the shifted out bit is set, and to `0` otherwise.
     ///
-    fn execute(&self, mut state: &mut Chip8) {
+    ///   For `Z == 0x6` and `Z == 0xE`, `state.registers[Y]` is shifted instead of
+    ///   `state.registers[X]` if the `shift_uses_vy` quirk is set.
+    ///
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
         fn modify_registers(
             state: &mut Chip8,
             r1: u8,
@@ -74,6 +90,12 @@ impl Instruction for RegInstruction {
             }
         }
 
+        let shift_source = if state.quirks.shift_uses_vy {
+            self.op2
+        } else {
+            self.op1
+        };
+
         match self.op3 {
             0x0 => modify_registers(&mut state, self.op1, self.op2, |_, v2| (v2, None)),
             0x1 => modify_registers(&mut state, self.op1, self.op2, |v1, v2| (v1 | v2, None)),
@@ -87,28 +109,69 @@ impl Instruction for RegInstruction {
                 let (result, overflow) = v1.overflowing_sub(v2);
                 (result, Some(!overflow))
             }),
-            0x6 => modify_registers(&mut state, self.op1, self.op2, |v1, _| {
-                (v1 >> 1, Some(v1 & 1 != 0))
+            0x6 => modify_registers(&mut state, self.op1, shift_source, |_, v2| {
+                (v2 >> 1, Some(v2 & 1 != 0))
             }),
             0x7 => modify_registers(&mut state, self.op1, self.op2, |v1, v2| {
                 let (result, overflow) = v2.overflowing_sub(v1);
                 (result, Some(!overflow))
             }),
-            0xE => modify_registers(&mut state, self.op1, self.op2, |v1, _| {
-                (v1 << 1, Some(v1 & 0x80 != 0))
+            0xE => modify_registers(&mut state, self.op1, shift_source, |_, v2| {
+                (v2 << 1, Some(v2 & 0x80 != 0))
             }),
-            _ => panic!("Unsupported opcode"),
+            _ => return Err(util::unknown_opcode(state)),
         };
+
+        if state.quirks.logic_resets_vf && (0x1..=0x3).contains(&self.op3) {
+            state.registers[0xF] = 0;
+        }
         util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        let operation = match self.op3 {
+            0x0 => "LD",
+            0x1 => "OR",
+            0x2 => "AND",
+            0x3 => "XOR",
+            0x4 => "ADD",
+            0x5 => "SUB",
+            0x6 => "SHR",
+            0x7 => "SUBN",
+            0xE => "SHL",
+            _ => return None,
+        };
+        Some(format!("{} V{:X}, V{:X}", operation, self.op1, self.op2))
     }
 }
 
 define_instruction_with_address!(Ld, LdInstruction, 0xA);
 impl Instruction for LdInstruction {
     /// Opcode of the form `0xAXYZ` (LD). Loads `XYZ` into `state.index`.
-    fn execute(&self, mut state: &mut Chip8) {
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
         state.index = self.address;
         util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!("LD I, {:#05X}", self.address))
+    }
+}
+
+define_instruction_with_long_address!(Ldl, LdlInstruction, 0xF);
+impl Instruction for LdlInstruction {
+    /// Opcode of the form `0xF000WXYZ` (LDL). Loads `WXYZ` into `state.index` (XO-CHIP).
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
+        state.index = self.address;
+        util::increment_program_counter(&mut state);
+        util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!("LD I, LONG {:#06X}", self.address))
     }
 }
 
@@ -116,13 +179,17 @@ define_instruction_with_reg_and_value!(Rnd, RndInstruction, 0xC);
 impl Instruction for RndInstruction {
     /// Opcode of the form `0xCXYZ` (RND). Generates a random value `v`, and sets
     /// `state.registers[X] = v & YZ.
-    fn execute(&self, mut state: &mut Chip8) {
-        let mut rng = thread_rng();
-        let sample = rng.gen_range(0, 255);
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
+        let sample = state.random.next_byte();
 
-        state.registers[self.reg as usize] = sample as u8 & self.value;
+        state.registers[self.reg as usize] = sample & self.value;
 
         util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!("RND V{:X}, {:#04X}", self.reg, self.value))
     }
 }
 
@@ -132,49 +199,117 @@ impl Instruction for DrwInstruction {
     ///
     /// - Drawing a pixel means flipping the pixel on the display.
     /// - The sprite is drawn at coordinate (`X`, `Y`) on the display.
-    /// - A sprite is of width `8` pixels and of height `Z` pixels.
+    /// - A sprite is of width `8` pixels and of height `Z` pixels. If `Z == 0`, the
+    ///   sprite is of width `16` pixels and of height `16` pixels, where each row is
+    ///   made up of two bytes (SUPER-CHIP).
     /// - The bits to be written are taken from the memory starting at the address
     ///   given in the index register.
     /// - `state.registers[0xF]` is set to `1` if any pixel is flipped to `0`, and
     ///   to `0` otherwise.
-    fn execute(&self, mut state: &mut Chip8) {
-        fn translate_gfx(x: u16, y: u16) -> usize {
-            ((x % 64) + ((y % 32) * 64)) as usize
-        }
+    /// - The sprite is drawn to each selected bitplane, starting with the first one.
+    ///   The bits for each subsequent bitplane directly follow the bits of the
+    ///   previous one in memory (XO-CHIP).
+    /// - The coordinate (`X`, `Y`) wraps around the edges of the display. Pixels of
+    ///   the sprite exceeding the edges are clipped if the `clip_sprites` quirk is set,
+    ///   and wrap around otherwise.
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
+        let (width, height) = state.resolution();
+        let row_mask = util::row_mask(width);
 
-        let x = state.registers[self.op1 as usize];
-        let y = state.registers[self.op2 as usize];
-        let n = self.op3;
+        let x = state.registers[self.op1 as usize] as usize % width;
+        let y = state.registers[self.op2 as usize] as usize % height;
+        let (sprite_width, sprite_height) = match self.op3 {
+            0 => (16, 16),
+            n => (8, n as usize),
+        };
+        let bytes_per_row = sprite_width / 8;
 
         state.registers[0xF] = 0;
-        for y_pos in 0..n {
-            let pixel_byte = state.memory[((state.index + y_pos as u16) % 4096) as usize];
-
-            let mut x_pos = 0;
-            let mut pixel_mask = 0x80;
+        let mut changed = false;
+        let mut sprite_address = state.index;
+        for plane in 0..XO_CHIP_PLANES {
+            if state.selected_planes & (1 << plane) == 0 {
+                continue;
+            }
 
-            while x_pos < 8 {
-                let pixel_bit = (pixel_byte & pixel_mask) > 0;
+            for y_pos in 0..sprite_height {
+                if state.quirks.clip_sprites && y + y_pos >= height {
+                    break;
+                }
 
-                let pixel_pos = translate_gfx(x as u16 + x_pos, y as u16 + y_pos as u16);
+                let mut pixel_row: u16 = 0;
+                for byte in 0..bytes_per_row {
+                    let address =
+                        sprite_address.wrapping_add((y_pos * bytes_per_row + byte) as u16);
+                    pixel_row = pixel_row << 8 | util::read_memory(state, address) as u16;
+                }
 
-                if pixel_bit != state.output_pins[pixel_pos] {
-                    state.draw = true;
+                // align the sprite row with the left edge of the display, shift it to
+                // column `x` and wrap the pixels exceeding the right edge around
+                let sprite = (pixel_row as u128) << (128 - sprite_width);
+                let mut bits = (sprite >> x) & row_mask;
+                if !state.quirks.clip_sprites {
+                    bits |= sprite.checked_shl((width - x) as u32).unwrap_or(0);
                 }
 
-                if pixel_bit {
-                    if state.output_pins[pixel_pos] {
-                        state.registers[0xF] = 1;
-                    }
-                    state.output_pins[pixel_pos] ^= true;
+                let row = &mut state.output_pins[plane][(y + y_pos) % height];
+                if *row & bits != 0 {
+                    state.registers[0xF] = 1;
+                }
+                if bits != 0 {
+                    *row ^= bits;
+                    changed = true;
                 }
+            }
+
+            sprite_address = sprite_address.wrapping_add((sprite_height * bytes_per_row) as u16);
+        }
 
-                x_pos += 1;
-                pixel_mask >>= 1;
+        if changed {
+            // the sprite covers up to four rectangles if it wraps around the edges
+            let wraps = !state.quirks.clip_sprites;
+            let columns = split_at_edge(x, sprite_width, width, wraps);
+            let rows = split_at_edge(y, sprite_height, height, wraps);
+            for (x, width) in columns.iter().flatten() {
+                for (y, height) in rows.iter().flatten() {
+                    let rect = Rect {
+                        x: *x,
+                        y: *y,
+                        width: *width,
+                        height: *height,
+                    };
+                    util::mark_dirty(state, rect);
+                }
             }
         }
         util::increment_program_counter(&mut state);
+        Ok(())
     }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!(
+            "DRW V{:X}, V{:X}, {}",
+            self.op1, self.op2, self.op3
+        ))
+    }
+}
+
+/// Splits the range of `len` pixels starting at `start` into the part before the edge
+/// of a display of size `size` and, if `wraps` is set, the part wrapping around to the
+/// start of the display. Each part is given by its start and its length.
+fn split_at_edge(
+    start: usize,
+    len: usize,
+    size: usize,
+    wraps: bool,
+) -> [Option<(usize, usize)>; 2] {
+    let end = start + len;
+    let wrapped = if wraps && end > size {
+        Some((0, end - size))
+    } else {
+        None
+    };
+    [Some((start, end.min(size) - start)), wrapped]
 }
 
 define_instruction_with_reg_and_value!(Ldu, LduInstruction, 0xF);
@@ -182,6 +317,12 @@ impl Instruction for LduInstruction {
     /// Opcode of the form `0xFXYZ` (LDU). Groups various load and store operations.
     /// `YZ` determines which operation is executed relative to `state`.
     ///
+    /// - If `YZ == 0x01`, select the bitplanes given by the bitmask `X` for drawing,
+    ///   clearing and scrolling (XO-CHIP).
+    ///
+    /// - If `XYZ == 0x002`, load the 16 bytes starting at `state.index` into the audio
+    ///   pattern buffer (XO-CHIP).
+    ///
     /// - If `YZ == 0x07`, set `state.registers[X] = delay_timer`.
     ///
     /// - If `YZ == 0x0A`, await key press and store in `state.registers[X]`.
@@ -195,6 +336,11 @@ impl Instruction for LduInstruction {
     ///
     /// - If `YZ == 0x29`, load the address of the character in `state.registers[X]` into `index`.
     ///
+    /// - If `YZ == 0x30`, load the address of the big character in `state.registers[X]` into
+    ///   `index` (SUPER-CHIP).
+    ///
+    /// - If `YZ == 0x3A`, set the pitch register to `state.registers[X]` (XO-CHIP).
+    ///
     /// - If `YZ == 0x33`, store the binary coded decimal representation of `state.registers[X]` in
     ///   `state.memory[index]`-`state.memory[index+2], where the most significant digit goes into
     ///   `state.memory[index]`.
@@ -205,8 +351,31 @@ impl Instruction for LduInstruction {
     /// - If `YZ == 0x65`, load `state.registers[0]` to `state.registers[X]` from memory starting
     ///   at `state.index`.
     ///
-    fn execute(&self, mut state: &mut Chip8) {
+    ///   For `YZ == 0x55` and `YZ == 0x65`, `state.index` is incremented according to the
+    ///   `load_store_index_increment` quirk.
+    ///
+    /// - If `YZ == 0x75`, store `state.registers[0]` to `state.registers[X]` in the RPL user
+    ///   flags (SUPER-CHIP).
+    ///
+    /// - If `YZ == 0x85`, load `state.registers[0]` to `state.registers[X]` from the RPL user
+    ///   flags (SUPER-CHIP).
+    ///
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
         match self.value {
+            0x01 => {
+                util::require_xo_chip(state)?;
+                if self.reg > 0x3 {
+                    return Err(util::unknown_opcode(state));
+                }
+                state.selected_planes = self.reg;
+            }
+            0x02 if self.reg == 0x0 => {
+                util::require_xo_chip(state)?;
+                for i in 0..XO_CHIP_AUDIO_PATTERN_LEN {
+                    let address = state.index.wrapping_add(i as u16);
+                    state.audio_pattern[i] = util::read_memory(state, address);
+                }
+            }
             0x07 => {
                 state.registers[self.reg as usize] = state.delay_timer;
             }
@@ -223,7 +392,7 @@ impl Instruction for LduInstruction {
                 if !input_pin_set {
                     // if no input pin was set, we directly return without
                     // incrementing the program counter
-                    return;
+                    return Ok(());
                 }
             }
             0x15 => {
@@ -238,34 +407,96 @@ impl Instruction for LduInstruction {
                     .wrapping_add(state.registers[self.reg as usize] as u16);
             }
             0x29 => {
-                let character: u16 = state.registers[self.reg as usize] as u16;
-                assert!(character <= 0xF);
-                state.index = CHIP8_CHARSET_OFFSET + character * 5;
+                let character = state.registers[self.reg as usize];
+                if character > 0xF {
+                    return Err(ExecutionError::InvalidFontDigit(character));
+                }
+                state.index = CHIP8_CHARSET_OFFSET + character as u16 * 5;
+            }
+            0x30 => {
+                let character = state.registers[self.reg as usize];
+                if character > 0xF {
+                    return Err(ExecutionError::InvalidFontDigit(character));
+                }
+                state.index = SCHIP_BIG_CHARSET_OFFSET + character as u16 * 10;
             }
             0x33 => {
                 let mut a: u8 = state.registers[self.reg as usize];
-                state.memory[(state.index + 2) as usize] = (a % 10) as u8;
+                let address = state.index.wrapping_add(2);
+                util::write_memory(state, address, (a % 10) as u8);
 
                 a /= 10;
-                state.memory[(state.index + 1) as usize] = (a % 10) as u8;
+                let address = state.index.wrapping_add(1);
+                util::write_memory(state, address, (a % 10) as u8);
 
                 a /= 10;
-                state.memory[state.index as usize] = (a % 10) as u8;
+                let address = state.index;
+                util::write_memory(state, address, (a % 10) as u8);
+            }
+            0x3A => {
+                util::require_xo_chip(state)?;
+                state.pitch = state.registers[self.reg as usize];
             }
             0x55 => {
                 for reg in 0x0..=self.reg {
-                    state.memory[((state.index + reg as u16) % 4096) as usize] =
-                        state.registers[reg as usize];
+                    let address = state.index.wrapping_add(reg as u16);
+                    util::write_memory(state, address, state.registers[reg as usize]);
                 }
+                increment_index(state, self.reg);
             }
             0x65 => {
                 for reg in 0x0..=self.reg {
-                    state.registers[reg as usize] =
-                        state.memory[((state.index + reg as u16) % 4096) as usize];
+                    let address = state.index.wrapping_add(reg as u16);
+                    state.registers[reg as usize] = util::read_memory(state, address);
                 }
+                increment_index(state, self.reg);
             }
-            _ => unimplemented!("Unsupported opcode"),
+            0x75 => {
+                for reg in 0x0..=self.reg {
+                    state.rpl_flags[reg as usize] = state.registers[reg as usize];
+                }
+            }
+            0x85 => {
+                for reg in 0x0..=self.reg {
+                    state.registers[reg as usize] = state.rpl_flags[reg as usize];
+                }
+            }
+            _ => return Err(util::unknown_opcode(state)),
         }
         util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        let x = self.reg;
+        match self.value {
+            0x01 if x <= 0x3 => Some(format!("PLANE {}", x)),
+            0x02 if x == 0x0 => Some("AUDIO".to_string()),
+            0x07 => Some(format!("LD V{:X}, DT", x)),
+            0x0A => Some(format!("LD V{:X}, K", x)),
+            0x15 => Some(format!("LD DT, V{:X}", x)),
+            0x18 => Some(format!("LD ST, V{:X}", x)),
+            0x1E => Some(format!("ADD I, V{:X}", x)),
+            0x29 => Some(format!("LD F, V{:X}", x)),
+            0x30 => Some(format!("LD HF, V{:X}", x)),
+            0x33 => Some(format!("LD B, V{:X}", x)),
+            0x3A => Some(format!("PITCH V{:X}", x)),
+            0x55 => Some(format!("LD [I], V{:X}", x)),
+            0x65 => Some(format!("LD V{:X}, [I]", x)),
+            0x75 => Some(format!("LD R, V{:X}", x)),
+            0x85 => Some(format!("LD V{:X}, R", x)),
+            _ => None,
+        }
     }
 }
+
+/// Increments `state.index` after loading or storing the registers up to `reg`,
+/// according to the `load_store_index_increment` quirk.
+fn increment_index(state: &mut Chip8, reg: u8) {
+    let increment = match state.quirks.load_store_index_increment {
+        IndexIncrement::None => 0,
+        IndexIncrement::X => reg as u16,
+        IndexIncrement::XPlusOne => reg as u16 + 1,
+    };
+    state.index = state.index.wrapping_add(increment);
+}
@@ -4,14 +4,13 @@ mod arithmetic_and_logic;
 mod program_flow;
 mod system;
 
-use core::convert::{Into, TryFrom};
 use std::marker::PhantomData;
 
 use crate::chip::chip8::{
     opcodes::{
         arithmetic_and_logic::{
-            AddInstruction, DrwInstruction, LdInstruction, LdrInstruction, LduInstruction,
-            RegInstruction, RndInstruction,
+            AddInstruction, DrwInstruction, LdInstruction, LdlInstruction, LdrInstruction,
+            LduInstruction, RegInstruction, RndInstruction,
         },
         program_flow::{
             CallInstruction, JmpInstruction, JmprInstruction, SeInstruction, SkInstruction,
@@ -21,6 +20,7 @@ use crate::chip::chip8::{
     },
     Chip8,
 };
+use crate::chip::ExecutionError;
 
 /// Represents a Chip 8 opcode. A Chip 8 opcode is two bytes long.  
 #[derive(Debug)]
@@ -32,6 +32,9 @@ pub(super) struct Opcode {
     instruction_class: u8,
     /// The payload constitutes the remaining nibbles of the opcode.
     payload: OpcodePayload,
+    /// The second half of a four byte XO-CHIP opcode, which is interpreted
+    /// as an address. It is `None` for regular two byte opcodes.
+    long_operand: Option<u16>,
 }
 
 /// Represents the payload of a Chip 8 opcode. That is the opcode without
@@ -75,94 +78,214 @@ impl Opcode {
             payload: OpcodePayload {
                 bytes: [opcode[0] & 0xF, opcode[1] >> 4, opcode[1] & 0xF],
             },
+            long_operand: None,
         }
     }
 
-    pub(super) fn execute(self, mut state: &mut Chip8) {
-        let executable_opcode: Box<dyn Instruction> = self.into();
-        executable_opcode.execute(&mut state);
+    /// Returns whether this opcode is the first half of a four byte XO-CHIP opcode,
+    /// i.e., whether it is `0xF000`.
+    pub(super) fn has_long_operand(&self) -> bool {
+        self.instruction_class == 0xF && self.payload.bytes == [0, 0, 0]
     }
-}
 
-impl From<Opcode> for Box<dyn Instruction> {
-    fn from(opcode: Opcode) -> Box<dyn Instruction> {
-        fn into_helper<T>(opcode: Opcode) -> Box<T>
-        where
-            T: Instruction + TryFrom<Opcode>,
-            <T as TryFrom<Opcode>>::Error: std::fmt::Debug,
-        {
-            // We can safely unwrap the converted instructions below as we know
-            // that the instruction class will map the respective instruction.
-            Box::new(T::try_from(opcode).unwrap())
+    /// Turns `self` into a four byte opcode with the given second half.
+    pub(super) fn with_long_operand(self, operand: &[u8; 2]) -> Opcode {
+        Opcode {
+            long_operand: Some((operand[0] as u16) << 8 | operand[1] as u16),
+            ..self
         }
+    }
 
-        match opcode.instruction_class {
-            0x0 => into_helper::<SysInstruction>(opcode),
-            0x1 => into_helper::<JmpInstruction>(opcode),
-            0x2 => into_helper::<CallInstruction>(opcode),
-            0x3 => into_helper::<SeInstruction>(opcode),
-            0x4 => into_helper::<SneInstruction>(opcode),
-            0x5 => into_helper::<SreInstruction>(opcode),
-            0x6 => into_helper::<LdrInstruction>(opcode),
-            0x7 => into_helper::<AddInstruction>(opcode),
-            0x8 => into_helper::<RegInstruction>(opcode),
-            0x9 => into_helper::<SrneInstruction>(opcode),
-            0xA => into_helper::<LdInstruction>(opcode),
-            0xB => into_helper::<JmprInstruction>(opcode),
-            0xC => into_helper::<RndInstruction>(opcode),
-            0xD => into_helper::<DrwInstruction>(opcode),
-            0xE => into_helper::<SkInstruction>(opcode),
-            0xF => into_helper::<LduInstruction>(opcode),
-            _ => unimplemented!("Unsupported opcode: {}", opcode),
+    /// Decodes the opcode into the instruction it represents, based on its
+    /// instruction class. Returns `None` if the instruction class is invalid.
+    pub(super) fn decode(self) -> Option<DecodedInstruction> {
+        let payload = &self.payload;
+        Some(match self.instruction_class {
+            0x0 => DecodedInstruction::Sys(SysInstruction::decode(payload)),
+            0x1 => DecodedInstruction::Jmp(JmpInstruction::decode(payload)),
+            0x2 => DecodedInstruction::Call(CallInstruction::decode(payload)),
+            0x3 => DecodedInstruction::Se(SeInstruction::decode(payload)),
+            0x4 => DecodedInstruction::Sne(SneInstruction::decode(payload)),
+            0x5 => DecodedInstruction::Sre(SreInstruction::decode(payload)),
+            0x6 => DecodedInstruction::Ldr(LdrInstruction::decode(payload)),
+            0x7 => DecodedInstruction::Add(AddInstruction::decode(payload)),
+            0x8 => DecodedInstruction::Reg(RegInstruction::decode(payload)),
+            0x9 => DecodedInstruction::Srne(SrneInstruction::decode(payload)),
+            0xA => DecodedInstruction::Ld(LdInstruction::decode(payload)),
+            0xB => DecodedInstruction::Jmpr(JmprInstruction::decode(payload)),
+            0xC => DecodedInstruction::Rnd(RndInstruction::decode(payload)),
+            0xD => DecodedInstruction::Drw(DrwInstruction::decode(payload)),
+            0xE => DecodedInstruction::Sk(SkInstruction::decode(payload)),
+            0xF => match self.long_operand {
+                Some(address) => DecodedInstruction::Ldl(LdlInstruction::decode(address)),
+                None => DecodedInstruction::Ldu(LduInstruction::decode(payload)),
+            },
+            0x10..=u8::MAX => return None,
+        })
+    }
+
+    /// Returns the length of the opcode in bytes.
+    pub(super) fn len(&self) -> u16 {
+        match self.long_operand {
+            Some(_) => 4,
+            None => 2,
         }
     }
+
+    /// Returns the mnemonic representation of the opcode (e.g., `JP 0x2A4`), or
+    /// `None` if the opcode is not supported.
+    pub(super) fn mnemonic(self) -> Option<String> {
+        self.decode()?.mnemonic()
+    }
 }
 
-/// Captures errors when converting opcodes to their respective instruction object.
-#[derive(Debug)]
-enum InstructionParsingError {
-    /// The given
-    InvalidInstructionClass(u8, u8),
+/// An instruction decoded from an opcode. Each variant holds the instruction of one
+/// instruction class, so that executing an instruction neither allocates nor goes
+/// through dynamic dispatch.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum DecodedInstruction {
+    Sys(SysInstruction),
+    Jmp(JmpInstruction),
+    Call(CallInstruction),
+    Se(SeInstruction),
+    Sne(SneInstruction),
+    Sre(SreInstruction),
+    Ldr(LdrInstruction),
+    Add(AddInstruction),
+    Reg(RegInstruction),
+    Srne(SrneInstruction),
+    Ld(LdInstruction),
+    Jmpr(JmprInstruction),
+    Rnd(RndInstruction),
+    Drw(DrwInstruction),
+    Sk(SkInstruction),
+    Ldl(LdlInstruction),
+    Ldu(LduInstruction),
+}
+
+/// Evaluates `$body` with `$instruction` bound to the instruction held by the
+/// `DecodedInstruction` `$decoded`.
+macro_rules! dispatch {
+    ($decoded:expr, $instruction:ident => $body:expr) => {
+        match $decoded {
+            DecodedInstruction::Sys($instruction) => $body,
+            DecodedInstruction::Jmp($instruction) => $body,
+            DecodedInstruction::Call($instruction) => $body,
+            DecodedInstruction::Se($instruction) => $body,
+            DecodedInstruction::Sne($instruction) => $body,
+            DecodedInstruction::Sre($instruction) => $body,
+            DecodedInstruction::Ldr($instruction) => $body,
+            DecodedInstruction::Add($instruction) => $body,
+            DecodedInstruction::Reg($instruction) => $body,
+            DecodedInstruction::Srne($instruction) => $body,
+            DecodedInstruction::Ld($instruction) => $body,
+            DecodedInstruction::Jmpr($instruction) => $body,
+            DecodedInstruction::Rnd($instruction) => $body,
+            DecodedInstruction::Drw($instruction) => $body,
+            DecodedInstruction::Sk($instruction) => $body,
+            DecodedInstruction::Ldl($instruction) => $body,
+            DecodedInstruction::Ldu($instruction) => $body,
+        }
+    };
+}
+
+impl DecodedInstruction {
+    /// Executes `self` relative to the given `state`. See `Instruction::execute`.
+    pub(super) fn execute(&self, state: &mut Chip8) -> Result<(), ExecutionError> {
+        dispatch!(self, instruction => instruction.execute(state))
+    }
+
+    /// Returns the mnemonic representation of `self`, or `None` if `self` does not
+    /// represent a supported instruction.
+    pub(super) fn mnemonic(&self) -> Option<String> {
+        dispatch!(self, instruction => instruction.mnemonic())
+    }
 }
 
 /// Represents an instruction that can be executed.
 trait Instruction {
     /// Executes `self` relative to the given `state`. Note that this
-    /// method will in-place modify the given state.
-    fn execute(&self, state: &mut Chip8);
+    /// method will in-place modify the given state. Returns an error if
+    /// the instruction cannot be executed relative to the given state.
+    fn execute(&self, state: &mut Chip8) -> Result<(), ExecutionError>;
+
+    /// Returns the mnemonic representation of `self`, or `None` if `self`
+    /// does not represent a supported instruction.
+    fn mnemonic(&self) -> Option<String>;
 }
 
 /// Represents an opcode that expects the payload to be an address.
-struct InstructionWithAddress<T> {
+#[derive(Debug, Clone, Copy)]
+pub(super) struct InstructionWithAddress<T> {
     instruction: PhantomData<T>,
     address: u16,
 }
 
 /// Represents an opcode that expects the payload to be three operands.
-struct InstructionWithOperands<T> {
+#[derive(Debug, Clone, Copy)]
+pub(super) struct InstructionWithOperands<T> {
     instruction: PhantomData<T>,
     op1: u8,
     op2: u8,
     op3: u8,
 }
 
+/// Represents a four byte opcode that expects the second half to be an address.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct InstructionWithLongAddress<T> {
+    instruction: PhantomData<T>,
+    address: u16,
+}
+
 /// Represents an opcode that expects the payload to be a register pointer and a value.
-struct InstructionWithRegAndValue<T> {
+#[derive(Debug, Clone, Copy)]
+pub(super) struct InstructionWithRegAndValue<T> {
     instruction: PhantomData<T>,
     reg: u8,
     value: u8,
 }
 
-impl std::fmt::Display for InstructionParsingError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            InstructionParsingError::InvalidInstructionClass(got, expected) => write!(
-                f,
-                "Error while parsing opcode. Instruction class does 
-                           not comply with target objects instruction class. 
-                           Got {}, expected {}.",
-                got, expected
-            ),
+impl<T> InstructionWithAddress<T> {
+    /// Decodes the instruction from the payload of its opcode.
+    fn decode(payload: &OpcodePayload) -> Self {
+        InstructionWithAddress {
+            instruction: PhantomData,
+            address: payload.address(),
+        }
+    }
+}
+
+impl<T> InstructionWithOperands<T> {
+    /// Decodes the instruction from the payload of its opcode.
+    fn decode(payload: &OpcodePayload) -> Self {
+        let (op1, op2, op3) = payload.operands();
+        InstructionWithOperands {
+            instruction: PhantomData,
+            op1,
+            op2,
+            op3,
+        }
+    }
+}
+
+impl<T> InstructionWithLongAddress<T> {
+    /// Decodes the instruction from the second half of its four byte opcode.
+    fn decode(address: u16) -> Self {
+        InstructionWithLongAddress {
+            instruction: PhantomData,
+            address,
+        }
+    }
+}
+
+impl<T> InstructionWithRegAndValue<T> {
+    /// Decodes the instruction from the payload of its opcode.
+    fn decode(payload: &OpcodePayload) -> Self {
+        let (reg, value) = payload.reg_and_value();
+        InstructionWithRegAndValue {
+            instruction: PhantomData,
+            reg,
+            value,
         }
     }
 }
@@ -1,85 +1,174 @@
-use core::convert::TryFrom;
-use std::marker::PhantomData;
-
 use crate::chip::chip8::{
     opcodes::{
-        Instruction, InstructionParsingError, InstructionWithAddress, InstructionWithOperands,
-        InstructionWithRegAndValue, Opcode,
+        Instruction, InstructionWithAddress, InstructionWithOperands, InstructionWithRegAndValue,
     },
     util, Chip8,
 };
+use crate::chip::ExecutionError;
 
 define_instruction_with_address!(Jmp, JmpInstruction, 0x1);
 impl Instruction for JmpInstruction {
     /// Opcode of the form `0x1XYZ` (JMP). Sets `state.program_counter` to `XYZ`.
-    fn execute(&self, state: &mut Chip8) {
+    fn execute(&self, state: &mut Chip8) -> Result<(), ExecutionError> {
         state.program_counter = self.address;
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!("JP {:#05X}", self.address))
     }
 }
 
 define_instruction_with_address!(Call, CallInstruction, 0x2);
 impl Instruction for CallInstruction {
     /// Opcode of the form `0x2XYZ` (CALL). Calls the routine at `XYZ`.
-    fn execute(&self, state: &mut Chip8) {
-        assert!(state.stack_pointer < 16, "Stack overflow");
+    fn execute(&self, state: &mut Chip8) -> Result<(), ExecutionError> {
+        if state.stack_pointer as usize >= state.stack.len() {
+            return Err(ExecutionError::StackOverflow);
+        }
         state.stack[state.stack_pointer as usize] = state.program_counter;
         state.stack_pointer = state.stack_pointer + 1;
         state.program_counter = self.address;
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!("CALL {:#05X}", self.address))
     }
 }
 
 define_instruction_with_reg_and_value!(Se, SeInstruction, 0x3);
 impl Instruction for SeInstruction {
     /// Opcode of the form `0x3XYZ` (SE). Skip the next instruction if `state.registers[X] == YZ`.
-    fn execute(&self, mut state: &mut Chip8) {
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
         util::conditional_skip(&self, &mut state, |instruction, state| {
             state.registers[instruction.reg as usize] == instruction.value
         });
         util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!("SE V{:X}, {:#04X}", self.reg, self.value))
     }
 }
 
 define_instruction_with_reg_and_value!(Sne, SneInstruction, 0x4);
 impl Instruction for SneInstruction {
     /// Opcode of the form `0x4XYZ` (SNE). Skip the next instruction if `state.registers[X] != YZ`.
-    fn execute(&self, mut state: &mut Chip8) {
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
         util::conditional_skip(&self, &mut state, |instruction, state| {
             state.registers[instruction.reg as usize] != instruction.value
         });
         util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!("SNE V{:X}, {:#04X}", self.reg, self.value))
     }
 }
 
 define_instruction_with_operands!(Sre, SreInstruction, 0x5);
 impl Instruction for SreInstruction {
-    /// Opcode of the form `0x5XY0` (SRE). Skip the next instruction if `state.registers[X] == state.registers[y]`.
-    fn execute(&self, mut state: &mut Chip8) {
-        util::conditional_skip(&self, &mut state, |instruction, state| {
-            assert_eq!(instruction.op3, 0, "Unsupported opcode");
-            state.registers[instruction.op1 as usize] == state.registers[instruction.op2 as usize]
-        });
+    /// Opcode of the form `0x5XYZ` (SRE). Groups the register comparison and the register
+    /// range operations.
+    ///
+    /// - If `Z == 0x0`, skip the next instruction if `state.registers[X] == state.registers[y]`.
+    ///
+    /// - If `Z == 0x2`, store `state.registers[X]` to `state.registers[Y]` in memory starting
+    ///   at `state.index` (XO-CHIP). If `X > Y`, the registers are stored in reverse order.
+    ///   `state.index` is left untouched.
+    ///
+    /// - If `Z == 0x3`, load `state.registers[X]` to `state.registers[Y]` from memory starting
+    ///   at `state.index` (XO-CHIP). If `X > Y`, the registers are loaded in reverse order.
+    ///   `state.index` is left untouched.
+    ///
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
+        match self.op3 {
+            0x0 => {
+                util::conditional_skip(&self, &mut state, |instruction, state| {
+                    state.registers[instruction.op1 as usize]
+                        == state.registers[instruction.op2 as usize]
+                });
+            }
+            0x2 => {
+                util::require_xo_chip(state)?;
+                for (offset, reg) in register_range(self.op1, self.op2).enumerate() {
+                    let address = state.index.wrapping_add(offset as u16);
+                    util::write_memory(state, address, state.registers[reg]);
+                }
+            }
+            0x3 => {
+                util::require_xo_chip(state)?;
+                for (offset, reg) in register_range(self.op1, self.op2).enumerate() {
+                    let address = state.index.wrapping_add(offset as u16);
+                    state.registers[reg] = util::read_memory(state, address);
+                }
+            }
+            _ => return Err(util::unknown_opcode(state)),
+        }
         util::increment_program_counter(&mut state);
+        Ok(())
     }
+
+    fn mnemonic(&self) -> Option<String> {
+        match self.op3 {
+            0x0 => Some(format!("SE V{:X}, V{:X}", self.op1, self.op2)),
+            0x2 => Some(format!("SAVE V{:X}, V{:X}", self.op1, self.op2)),
+            0x3 => Some(format!("LOAD V{:X}, V{:X}", self.op1, self.op2)),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the indices of the registers from `from` to `to` (inclusive), in descending
+/// order if `from > to`.
+fn register_range(from: u8, to: u8) -> impl Iterator<Item = usize> {
+    let step: isize = if from <= to { 1 } else { -1 };
+    let count = (to as isize - from as isize).abs() + 1;
+    (0..count).map(move |i| (from as isize + i * step) as usize)
 }
 
 define_instruction_with_operands!(Srne, SrneInstruction, 0x9);
 impl Instruction for SrneInstruction {
     /// Opcode of the form `0x9XY0` (SRNE). Skip the next instruction if `state.registers[X] != state.registers[Y]`.
-    fn execute(&self, mut state: &mut Chip8) {
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
+        if self.op3 != 0 {
+            return Err(util::unknown_opcode(state));
+        }
         util::conditional_skip(&self, &mut state, |instruction, state| {
-            assert_eq!(instruction.op3, 0, "Unsupported opcode");
             state.registers[instruction.op1 as usize] != state.registers[instruction.op2 as usize]
         });
         util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        match self.op3 {
+            0x0 => Some(format!("SNE V{:X}, V{:X}", self.op1, self.op2)),
+            _ => None,
+        }
     }
 }
 
 define_instruction_with_address!(Jmpr, JmprInstruction, 0xB);
 impl Instruction for JmprInstruction {
     /// Opcode of the form `0xBXYZ` (JMPR). Sets `state.program_counter` to `XYZ + state.registers[0]`
-    /// (where the addition wraps around if an overflow occurs).
-    fn execute(&self, mut state: &mut Chip8) {
-        state.program_counter = self.address.wrapping_add(state.registers[0] as u16);
+    /// (where the addition wraps around if an overflow occurs). If the `jump_uses_vx` quirk is set,
+    /// `state.registers[X]` is used instead of `state.registers[0]`.
+    fn execute(&self, state: &mut Chip8) -> Result<(), ExecutionError> {
+        let reg = if state.quirks.jump_uses_vx {
+            (self.address >> 8) as usize
+        } else {
+            0
+        };
+        state.program_counter = self.address.wrapping_add(state.registers[reg] as u16);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        Some(format!("JP V0, {:#05X}", self.address))
     }
 }
 
@@ -92,15 +181,27 @@ impl Instruction for SkInstruction {
     ///
     /// - If `YZ == A1`, it skips the next instruction if the key stored in `state.registers[X]`
     ///   is not pressed.
-    fn execute(&self, mut state: &mut Chip8) {
+    ///
+    /// Note that only the least significant nibble of `state.registers[X]` is considered.
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
+        let pin = (state.registers[self.reg as usize] & 0xF) as usize;
         let skip = match self.value {
-            0x9E => state.input_pins[state.registers[self.reg as usize] as usize],
-            0xA1 => !state.input_pins[state.registers[self.reg as usize] as usize],
-            _ => unimplemented!("Unsupported opcode"),
+            0x9E => state.input_pins[pin],
+            0xA1 => !state.input_pins[pin],
+            _ => return Err(util::unknown_opcode(state)),
         };
         if skip {
-            util::increment_program_counter(&mut state);
+            util::skip_next_instruction(&mut state);
         }
         util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        match self.value {
+            0x9E => Some(format!("SKP V{:X}", self.reg)),
+            0xA1 => Some(format!("SKNP V{:X}", self.reg)),
+            _ => None,
+        }
     }
 }
@@ -1,32 +1,131 @@
-use core::convert::TryFrom;
-use std::marker::PhantomData;
-
 use crate::chip::chip8::{
-    opcodes::{Instruction, InstructionParsingError, InstructionWithAddress, Opcode},
+    constants::{SCHIP_HIRES_HEIGHT, XO_CHIP_PLANES},
+    opcodes::{Instruction, InstructionWithAddress},
     util, Chip8,
 };
+use crate::chip::ExecutionError;
 
 define_instruction_with_address!(Sys, SysInstruction, 0x0);
 impl Instruction for SysInstruction {
     /// Opcode of the form `0x0XYZ` (SYS). Groups various system instructions.
     ///
+    /// - If `XY == 0x0C`, it scrolls the display down by `Z` pixels (SUPER-CHIP).
+    ///
+    /// - If `XY == 0x0D`, it scrolls the display up by `Z` pixels (XO-CHIP).
+    ///
     /// - If `XYZ == 0x0E0`, it clears the display.
     ///
     /// - If `XYZ == 0x0EE`, it returns from the current subroutine.
     ///
-    fn execute(&self, mut state: &mut Chip8) {
+    /// - If `XYZ == 0x0FB`, it scrolls the display right by 4 pixels (SUPER-CHIP).
+    ///
+    /// - If `XYZ == 0x0FC`, it scrolls the display left by 4 pixels (SUPER-CHIP).
+    ///
+    /// - If `XYZ == 0x0FD`, it exits the program (SUPER-CHIP).
+    ///
+    /// - If `XYZ == 0x0FE`, it switches to low resolution mode and clears the display
+    ///   (SUPER-CHIP).
+    ///
+    /// - If `XYZ == 0x0FF`, it switches to high resolution mode and clears the display
+    ///   (SUPER-CHIP).
+    ///
+    /// Note that clearing the display and scrolling only affect the selected bitplanes,
+    /// whereas switching the resolution clears all bitplanes.
+    ///
+    fn execute(&self, mut state: &mut Chip8) -> Result<(), ExecutionError> {
         match self.address {
+            0x0C0..=0x0CF => scroll(&mut state, 0, (self.address & 0xF) as isize),
+            0x0D0..=0x0DF => {
+                util::require_xo_chip(state)?;
+                scroll(&mut state, 0, -((self.address & 0xF) as isize));
+            }
             0x0E0 => {
-                state.output_pins = [false; 64 * 32];
-                state.program_counter += 2;
+                let planes = state.selected_planes;
+                clear(&mut state, planes);
             }
             0x0EE => {
-                assert!(state.stack_pointer > 0, "Stack underflow");
+                if state.stack_pointer == 0 {
+                    return Err(ExecutionError::StackUnderflow);
+                }
                 state.program_counter = state.stack[(state.stack_pointer - 1) as usize];
                 state.stack_pointer = state.stack_pointer - 1;
-                util::increment_program_counter(&mut state);
             }
-            _ => panic!("Opcode not supported"),
+            0x0FB => scroll(&mut state, 4, 0),
+            0x0FC => scroll(&mut state, -4, 0),
+            0x0FD => {
+                // the program counter is not incremented so that the state
+                // keeps pointing to the exit instruction
+                state.exited = true;
+                return Ok(());
+            }
+            0x0FE => {
+                state.hires = false;
+                clear(&mut state, ALL_PLANES);
+            }
+            0x0FF => {
+                state.hires = true;
+                clear(&mut state, ALL_PLANES);
+            }
+            _ => return Err(util::unknown_opcode(state)),
         };
+        util::increment_program_counter(&mut state);
+        Ok(())
+    }
+
+    fn mnemonic(&self) -> Option<String> {
+        match self.address {
+            0x0C0..=0x0CF => Some(format!("SCD {}", self.address & 0xF)),
+            0x0D0..=0x0DF => Some(format!("SCU {}", self.address & 0xF)),
+            0x0E0 => Some("CLS".to_string()),
+            0x0EE => Some("RET".to_string()),
+            0x0FB => Some("SCR".to_string()),
+            0x0FC => Some("SCL".to_string()),
+            0x0FD => Some("EXIT".to_string()),
+            0x0FE => Some("LOW".to_string()),
+            0x0FF => Some("HIGH".to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// A bitmask selecting all bitplanes.
+const ALL_PLANES: u8 = 0xFF;
+
+/// Clears the bitplanes of the display selected by the bitmask `planes`.
+fn clear(state: &mut Chip8, planes: u8) {
+    for plane in 0..XO_CHIP_PLANES {
+        if planes & (1 << plane) != 0 {
+            state.output_pins[plane] = [0; SCHIP_HIRES_HEIGHT];
+        }
+    }
+    util::mark_display_dirty(state);
+}
+
+/// Scrolls the selected bitplanes of the display by `dx` pixels to the right and `dy`
+/// pixels down relative to the current resolution. Pixels scrolled out of the display
+/// are discarded and pixels scrolled in are cleared.
+fn scroll(state: &mut Chip8, dx: isize, dy: isize) {
+    let (width, height) = state.resolution();
+    let row_mask = util::row_mask(width);
+    for plane in 0..XO_CHIP_PLANES {
+        if state.selected_planes & (1 << plane) == 0 {
+            continue;
+        }
+
+        let mut scrolled = [0; SCHIP_HIRES_HEIGHT];
+        for (y, row) in scrolled.iter_mut().enumerate().take(height) {
+            let source_y = y as isize - dy;
+            if source_y < 0 || source_y as usize >= height {
+                continue;
+            }
+            let source = state.output_pins[plane][source_y as usize];
+            *row = if dx >= 0 {
+                (source >> dx) & row_mask
+            } else {
+                source << -dx
+            };
+        }
+        state.output_pins[plane] = scrolled;
     }
+    util::mark_display_dirty(state);
 }
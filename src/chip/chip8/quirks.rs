@@ -0,0 +1,126 @@
+/// Configures the behaviour of CHIP-8 instructions whose semantics differ between
+/// interpreters. Different programs expect different behaviours, so the quirks have
+/// to be chosen to match the interpreter a program was written for. The presets
+/// `cosmac_vip`, `chip48`, `super_chip`, `xo_chip` and `modern` cover the most common cases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// If set, `0x8XY6` and `0x8XYE` shift `state.registers[Y]` and store the result
+    /// in `state.registers[X]`. Otherwise, `state.registers[X]` is shifted in place.
+    pub shift_uses_vy: bool,
+
+    /// How much `0xFX55` and `0xFX65` increment `state.index` by.
+    pub load_store_index_increment: IndexIncrement,
+
+    /// If set, `0xBXYZ` jumps to `XYZ + state.registers[X]`. Otherwise, it jumps to
+    /// `XYZ + state.registers[0]`.
+    pub jump_uses_vx: bool,
+
+    /// If set, `0x8XY1`, `0x8XY2` and `0x8XY3` set `state.registers[0xF]` to `0`.
+    /// Otherwise, `state.registers[0xF]` is left untouched.
+    pub logic_resets_vf: bool,
+
+    /// If set, sprites drawn by `0xDXYZ` are clipped at the edges of the display.
+    /// Otherwise, they wrap around to the opposite edge. Note that the coordinate
+    /// at which a sprite is drawn always wraps around.
+    pub clip_sprites: bool,
+}
+
+/// The amount by which the load and store instructions `0xFX55` and `0xFX65`
+/// increment `state.index` after accessing the memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// `state.index` is left untouched.
+    None,
+    /// `state.index` is incremented by `X`.
+    X,
+    /// `state.index` is incremented by `X + 1`, i.e., it points to the address
+    /// following the last register loaded or stored.
+    XPlusOne,
+}
+
+/// The presets along with the names accepted by `Quirks::preset`.
+const PRESETS: [(&str, Quirks); 5] = [
+    ("cosmac-vip", Quirks::cosmac_vip()),
+    ("chip48", Quirks::chip48()),
+    ("super-chip", Quirks::super_chip()),
+    ("xo-chip", Quirks::xo_chip()),
+    ("modern", Quirks::modern()),
+];
+
+impl Quirks {
+    /// Returns the preset called `name`, which is one of `cosmac-vip`, `chip48`,
+    /// `super-chip`, `xo-chip` and `modern`.
+    pub fn preset(name: &str) -> Option<Quirks> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, quirks)| *quirks)
+    }
+
+    /// Returns the names of the presets.
+    pub fn preset_names() -> Vec<&'static str> {
+        PRESETS.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// The behaviour of the original CHIP-8 interpreter on the COSMAC VIP.
+    pub const fn cosmac_vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_index_increment: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            logic_resets_vf: true,
+            clip_sprites: true,
+        }
+    }
+
+    /// The behaviour of the CHIP-48 interpreter on the HP-48 calculators.
+    pub const fn chip48() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_index_increment: IndexIncrement::X,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+        }
+    }
+
+    /// The behaviour of the SUPER-CHIP 1.1 interpreter.
+    pub const fn super_chip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_index_increment: IndexIncrement::None,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+        }
+    }
+
+    /// The behaviour of the XO-CHIP as implemented by Octo.
+    pub const fn xo_chip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_index_increment: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+        }
+    }
+
+    /// The behaviour most modern interpreters default to. This is also the behaviour
+    /// this emulator implemented before quirks were configurable.
+    pub const fn modern() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_index_increment: IndexIncrement::None,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            clip_sprites: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::modern()
+    }
+}
@@ -0,0 +1,55 @@
+use crate::chip::LoadStateError;
+
+/// Represents a source of random bytes as used by the RND instruction. Sources
+/// have to be able to serialize their state, so that a machine restored from a
+/// save state continues with the same sequence of random bytes.
+pub trait RandomSource: Send {
+    /// Returns the next random byte. All values from `0x00` to `0xFF` have to be
+    /// possible.
+    fn next_byte(&mut self) -> u8;
+
+    /// Serializes the state of the source.
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Restores a state serialized by `save_state`. Returns an error without
+    /// modifying the source if the state is invalid.
+    fn load_state(&mut self, state: &[u8]) -> Result<(), LoadStateError>;
+}
+
+/// The SplitMix64 generator, a fast generator with a 64 bit state. Note that it
+/// is not suitable for cryptographic purposes.
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    /// Constructs a new generator. Generators constructed with the same `seed`
+    /// produce the same sequence of bytes.
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_byte(&mut self) -> u8 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.state.to_be_bytes().to_vec()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), LoadStateError> {
+        let mut bytes = [0; 8];
+        if state.len() != bytes.len() {
+            return Err(LoadStateError::InvalidFormat);
+        }
+        bytes.copy_from_slice(state);
+        self.state = u64::from_be_bytes(bytes);
+        Ok(())
+    }
+}
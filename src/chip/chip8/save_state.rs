@@ -0,0 +1,247 @@
+use crate::chip::{
+    chip8::constants::{
+        SAVE_STATE_HEADER_LEN, SAVE_STATE_MAGIC, SAVE_STATE_VERSION, SCHIP_RPL_FLAGS,
+        XO_CHIP_AUDIO_PATTERN_LEN, XO_CHIP_PLANES,
+    },
+    chip8::{
+        quirks::{IndexIncrement, Quirks},
+        util,
+        variant::Variant,
+        Chip8,
+    },
+    LoadStateError,
+};
+
+/// Serializes the complete state of `chip8`. The snapshot consists of a header
+/// holding `SAVE_STATE_MAGIC`, `SAVE_STATE_VERSION` and the length of the payload
+/// (as big-endian u32), followed by the payload and a CRC-32 checksum (as
+/// big-endian u32) of the header and the payload. The audio sink, the debugger,
+/// the decode cache and the dirty rectangles are not part of the state of the
+/// machine and hence not included.
+pub(super) fn save_state(chip8: &Chip8) -> Vec<u8> {
+    let mut payload = vec![];
+    payload.push(variant_to_byte(chip8.variant));
+    payload.push(quirks_to_byte(&chip8.quirks));
+    payload.extend_from_slice(&chip8.registers);
+    payload.extend_from_slice(&chip8.index.to_be_bytes());
+    payload.extend_from_slice(&chip8.program_counter.to_be_bytes());
+    for address in chip8.stack.iter() {
+        payload.extend_from_slice(&address.to_be_bytes());
+    }
+    payload.push(chip8.stack_pointer);
+    payload.push(chip8.delay_timer);
+    payload.push(chip8.sound_timer);
+    payload.push(chip8.hires as u8 | (chip8.exited as u8) << 1);
+    payload.push(chip8.selected_planes);
+    payload.extend_from_slice(&pack_bits(&chip8.input_pins));
+    payload.extend_from_slice(&chip8.rpl_flags);
+    payload.extend_from_slice(&chip8.audio_pattern);
+    payload.push(chip8.pitch);
+    let random_state = chip8.random.save_state();
+    payload.push(random_state.len() as u8);
+    payload.extend_from_slice(&random_state);
+    for row in chip8.output_pins.iter().flatten() {
+        payload.extend_from_slice(&row.to_be_bytes());
+    }
+    payload.extend_from_slice(&chip8.memory);
+
+    let mut state = Vec::with_capacity(SAVE_STATE_HEADER_LEN + payload.len() + 4);
+    state.extend_from_slice(&SAVE_STATE_MAGIC);
+    state.extend_from_slice(&SAVE_STATE_VERSION.to_be_bytes());
+    state.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    state.extend_from_slice(&payload);
+    let checksum = crc32(&state);
+    state.extend_from_slice(&checksum.to_be_bytes());
+    state
+}
+
+/// Deserializes a snapshot created by `save_state`. Returns an error if the
+/// snapshot is malformed, was created by an unsupported version, or does not
+/// match its checksum. As the type of the random source is not part of the
+/// snapshot, its serialized state is returned separately.
+pub(super) fn load_state(state: &[u8]) -> Result<(Chip8, &[u8]), LoadStateError> {
+    if state.len() < SAVE_STATE_HEADER_LEN + 4 || state[..4] != SAVE_STATE_MAGIC {
+        return Err(LoadStateError::InvalidFormat);
+    }
+    let version = u16::from_be_bytes([state[4], state[5]]);
+    if version != SAVE_STATE_VERSION {
+        return Err(LoadStateError::UnsupportedVersion(version));
+    }
+    let payload_len = u32::from_be_bytes([state[6], state[7], state[8], state[9]]) as usize;
+    if state.len() != SAVE_STATE_HEADER_LEN + payload_len + 4 {
+        return Err(LoadStateError::InvalidFormat);
+    }
+    let (content, checksum) = state.split_at(SAVE_STATE_HEADER_LEN + payload_len);
+    if crc32(content) != u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) {
+        return Err(LoadStateError::ChecksumMismatch);
+    }
+
+    let mut reader = Reader {
+        bytes: &content[SAVE_STATE_HEADER_LEN..],
+    };
+    let variant = byte_to_variant(reader.u8()?)?;
+    let quirks = byte_to_quirks(reader.u8()?)?;
+    let mut chip8 = Chip8::with_variant(variant, quirks);
+
+    chip8.registers.copy_from_slice(reader.bytes(16)?);
+    chip8.index = reader.u16()?;
+    chip8.program_counter = reader.u16()?;
+    for address in chip8.stack.iter_mut() {
+        *address = reader.u16()?;
+    }
+    chip8.stack_pointer = reader.u8()?;
+    chip8.delay_timer = reader.u8()?;
+    chip8.sound_timer = reader.u8()?;
+    let flags = reader.u8()?;
+    chip8.hires = flags & 0x1 != 0;
+    chip8.exited = flags & 0x2 != 0;
+    chip8.selected_planes = reader.u8()?;
+    unpack_bits(reader.bytes(2)?, &mut chip8.input_pins);
+    chip8
+        .rpl_flags
+        .copy_from_slice(reader.bytes(SCHIP_RPL_FLAGS)?);
+    chip8
+        .audio_pattern
+        .copy_from_slice(reader.bytes(XO_CHIP_AUDIO_PATTERN_LEN)?);
+    chip8.pitch = reader.u8()?;
+    let random_state_len = reader.u8()? as usize;
+    let random_state = reader.bytes(random_state_len)?;
+    for row in chip8.output_pins.iter_mut().flatten() {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(reader.bytes(16)?);
+        *row = u128::from_be_bytes(bytes);
+    }
+    let memory_size = chip8.memory.len();
+    chip8.memory.copy_from_slice(reader.bytes(memory_size)?);
+
+    if !reader.bytes.is_empty()
+        || chip8.stack_pointer as usize > chip8.stack.len()
+        || chip8.selected_planes as usize >= 1 << XO_CHIP_PLANES
+        || flags & !0x3 != 0
+        || !output_pins_in_resolution(&chip8)
+    {
+        return Err(LoadStateError::InvalidFormat);
+    }
+
+    // the output pins changed with respect to whatever was drawn before
+    util::mark_display_dirty(&mut chip8);
+    Ok((chip8, random_state))
+}
+
+/// Checks that no output pins outside of the current resolution of `chip8` are set.
+fn output_pins_in_resolution(chip8: &Chip8) -> bool {
+    let (width, height) = chip8.resolution();
+    let row_mask = util::row_mask(width);
+    chip8.output_pins.iter().all(|plane| {
+        plane
+            .iter()
+            .enumerate()
+            .all(|(y, row)| *row == 0 || (y < height && row & !row_mask == 0))
+    })
+}
+
+/// Reads values from the payload of a snapshot.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], LoadStateError> {
+        if self.bytes.len() < len {
+            return Err(LoadStateError::InvalidFormat);
+        }
+        let (bytes, remaining) = self.bytes.split_at(len);
+        self.bytes = remaining;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadStateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+fn variant_to_byte(variant: Variant) -> u8 {
+    match variant {
+        Variant::Chip8 => 0,
+        Variant::XoChip => 1,
+    }
+}
+
+fn byte_to_variant(byte: u8) -> Result<Variant, LoadStateError> {
+    match byte {
+        0 => Ok(Variant::Chip8),
+        1 => Ok(Variant::XoChip),
+        _ => Err(LoadStateError::InvalidFormat),
+    }
+}
+
+fn quirks_to_byte(quirks: &Quirks) -> u8 {
+    let index_increment = match quirks.load_store_index_increment {
+        IndexIncrement::None => 0,
+        IndexIncrement::X => 1,
+        IndexIncrement::XPlusOne => 2,
+    };
+    pack_bits(&[
+        quirks.shift_uses_vy,
+        quirks.jump_uses_vx,
+        quirks.logic_resets_vf,
+        quirks.clip_sprites,
+    ])[0]
+        | index_increment << 4
+}
+
+fn byte_to_quirks(byte: u8) -> Result<Quirks, LoadStateError> {
+    let mut flags = [false; 4];
+    unpack_bits(&[byte], &mut flags);
+    let load_store_index_increment = match byte >> 4 {
+        0 => IndexIncrement::None,
+        1 => IndexIncrement::X,
+        2 => IndexIncrement::XPlusOne,
+        _ => return Err(LoadStateError::InvalidFormat),
+    };
+    Ok(Quirks {
+        shift_uses_vy: flags[0],
+        load_store_index_increment,
+        jump_uses_vx: flags[1],
+        logic_resets_vf: flags[2],
+        clip_sprites: flags[3],
+    })
+}
+
+/// Packs the given bits into bytes, where bit `i` is stored in bit `i % 8` of
+/// byte `i / 8`.
+fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut bytes = vec![0; bits.len().div_ceil(8)];
+    for (i, bit) in bits.iter().enumerate() {
+        bytes[i / 8] |= (*bit as u8) << (i % 8);
+    }
+    bytes
+}
+
+/// Unpacks bytes created by `pack_bits` into `bits`.
+fn unpack_bits(bytes: &[u8], bits: &mut [bool]) {
+    for (i, bit) in bits.iter_mut().enumerate() {
+        *bit = bytes[i / 8] & (1 << (i % 8)) != 0;
+    }
+}
+
+/// Computes the CRC-32 (as used by zlib and PNG) of the given bytes.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
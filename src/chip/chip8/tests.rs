@@ -1,29 +1,79 @@
+use crate::chip::chip8::assembler::{assemble, assemble_file, AssemblerError, Location};
+use crate::chip::chip8::constants::SAVE_STATE_VERSION;
+use crate::chip::chip8::debugger::{Breakpoint, ParseError, StopReason, Watchpoint};
+use crate::chip::chip8::disassembler::{disassemble, reachable_addresses};
+use crate::chip::chip8::quirks::Quirks;
+use crate::chip::chip8::random::RandomSource;
+use crate::chip::chip8::variant::Variant;
 use crate::chip::chip8::Chip8;
-use crate::chip::chip8::CHIP8_CHARSET_OFFSET;
-use crate::chip::Chip;
+use crate::chip::chip8::{CHIP8_CHARSET_OFFSET, SCHIP_BIG_CHARSET_OFFSET};
+use crate::chip::scheduler::Scheduler;
+use crate::chip::{Chip, ExecutionError, LoadProgramError, LoadStateError};
 
 use rand::{thread_rng, Rng};
 use std::convert::TryInto;
 
 /// Prepares a new CHIP-8 with a program consisting of a single instruction
 fn prepare_state_with_single_instruction(instruction: u16) -> Chip8 {
-    let mut chip8 = Chip8::new();
+    prepare_state_with_single_instruction_and_quirks(instruction, Quirks::default())
+}
+
+/// Prepares a new CHIP-8 using the given `quirks` with a program consisting of a single
+/// instruction
+fn prepare_state_with_single_instruction_and_quirks(instruction: u16, quirks: Quirks) -> Chip8 {
+    let mut chip8 = Chip8::new(quirks);
     chip8.memory[0x200] = ((instruction & 0xFF00) >> 8) as u8;
     chip8.memory[0x201] = (instruction & 0xFF) as u8;
     chip8
 }
 
+/// Prepares a new CHIP-8 with the given program loaded.
+fn prepare_state_with_program(program: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::new(Quirks::default());
+    chip8.load_program(program).expect("Loading program failed");
+    chip8
+}
+
+/// Sets the output pin in column `x` and row `y` of the given bitplane.
+fn set_output_pin(state: &mut Chip8, plane: usize, x: usize, y: usize) {
+    state.output_pins[plane][y] |= 1 << (127 - x);
+}
+
 /// Obtains a new CHIP-8 with the given `instruction` as the program. Applies `before_cycle` to
 /// the state, performs one cycle using the state after calling `before_cycle`, and applies
 /// `after_cycle` to the state after the cycle.
 fn do_cycle(instruction: u16, before_cycle: impl Fn(&mut Chip8), after_cycle: impl Fn(&mut Chip8)) {
-    let mut state = prepare_state_with_single_instruction(instruction);
+    do_cycle_with_quirks(instruction, Quirks::default(), before_cycle, after_cycle);
+}
+
+/// Like `do_cycle`, but uses a CHIP-8 configured with the given `quirks`.
+fn do_cycle_with_quirks(
+    instruction: u16,
+    quirks: Quirks,
+    before_cycle: impl Fn(&mut Chip8),
+    after_cycle: impl Fn(&mut Chip8),
+) {
+    let mut state = prepare_state_with_single_instruction_and_quirks(instruction, quirks);
 
     before_cycle(&mut state);
-    state.cycle();
+    state.cycle().expect("Cycle failed");
     after_cycle(&mut state);
 }
 
+/// Obtains a new CHIP-8 with the given `instruction` as the program. Applies `before_cycle` to
+/// the state, performs one cycle using the state after calling `before_cycle`, and asserts that
+/// the cycle fails with `expected_error`.
+fn do_failing_cycle(
+    instruction: u16,
+    before_cycle: impl Fn(&mut Chip8),
+    expected_error: ExecutionError,
+) {
+    let mut state = prepare_state_with_single_instruction(instruction);
+
+    before_cycle(&mut state);
+    assert_eq!(state.cycle(), Err(expected_error));
+}
+
 #[test]
 fn test_jump() {
     do_cycle(
@@ -329,10 +379,6 @@ fn test_set_program_counter() {
 
 #[test]
 fn test_draw_sprite() {
-    fn translate_gfx(x: u16, y: u16) -> usize {
-        ((x % 64) + ((y % 32) * 64)) as usize
-    }
-
     do_cycle(
         0xD015,
         |state| {
@@ -345,10 +391,9 @@ fn test_draw_sprite() {
             for y in 0..5 {
                 let mut mask = 0x80;
                 for x in 0..8 {
-                    let index = translate_gfx(x, y);
                     assert_eq!(
                         state.memory[((state.index + y) % 4096) as usize] & mask > 0,
-                        state.output_pins[index]
+                        state.output_pin(0, x, y as usize)
                     );
                     mask >>= 1;
                 }
@@ -481,6 +526,28 @@ fn test_set_sound_timer() {
     }
 }
 
+#[test]
+fn test_tick_timers() {
+    let mut state = prepare_state_with_single_instruction(0x1200);
+    state.delay_timer = 2;
+    state.sound_timer = 1;
+
+    // cycles do not affect the timers
+    for _ in 0..100 {
+        state.cycle().expect("Cycle failed");
+    }
+    assert_eq!(state.delay_timer, 2);
+    assert_eq!(state.sound_timer, 1);
+
+    state.tick_timers();
+    assert_eq!(state.delay_timer, 1);
+    assert_eq!(state.sound_timer, 0);
+
+    state.tick_timers();
+    assert_eq!(state.delay_timer, 0);
+    assert_eq!(state.sound_timer, 0);
+}
+
 #[test]
 fn test_inc_index_by_reg() {
     for reg in 0x0..=0xF {
@@ -598,3 +665,1199 @@ fn test_reg_dump_load() {
         );
     }
 }
+
+#[test]
+fn test_unknown_opcode() {
+    for instruction in &[
+        0x0123, 0x5121, 0x812F, 0x9AB1, 0xE19F, 0xE0A2, 0xF0FF, 0xF256,
+    ] {
+        do_failing_cycle(
+            *instruction,
+            |_| {},
+            ExecutionError::UnknownOpcode(
+                0x200,
+                [(instruction >> 8) as u8, (instruction & 0xFF) as u8],
+            ),
+        );
+    }
+}
+
+#[test]
+fn test_stack_overflow() {
+    do_failing_cycle(
+        0x2CAF,
+        |state| {
+            state.stack_pointer = 16;
+        },
+        ExecutionError::StackOverflow,
+    );
+}
+
+#[test]
+fn test_stack_underflow() {
+    do_failing_cycle(0x00EE, |_| {}, ExecutionError::StackUnderflow);
+}
+
+#[test]
+fn test_program_counter_out_of_bounds() {
+    do_failing_cycle(
+        0x0000,
+        |state| {
+            state.program_counter = 0xFFF;
+        },
+        ExecutionError::ProgramCounterOutOfBounds(0xFFF),
+    );
+
+    do_failing_cycle(
+        0x0000,
+        |state| {
+            state.program_counter = 0xFFE;
+            state.memory[0xFFE] = 0x00;
+            state.memory[0xFFF] = 0xE0;
+            state.cycle().expect("Cycle failed");
+        },
+        ExecutionError::ProgramCounterOutOfBounds(0x1000),
+    );
+}
+
+#[test]
+fn test_invalid_font_digit() {
+    for reg in 0x0..=0xF {
+        let instruction = 0xF029 as u16 | (reg << 8) as u16;
+        do_failing_cycle(
+            instruction,
+            |state| {
+                state.registers[reg] = 0x10;
+            },
+            ExecutionError::InvalidFontDigit(0x10),
+        );
+    }
+}
+
+#[test]
+fn test_quirks_presets() {
+    assert_eq!(Quirks::preset("cosmac-vip"), Some(Quirks::cosmac_vip()));
+    assert_eq!(Quirks::preset("XO-CHIP"), Some(Quirks::xo_chip()));
+    assert_eq!(Quirks::preset("octo"), None);
+    let presets: Vec<_> = Quirks::preset_names()
+        .into_iter()
+        .map(|name| Quirks::preset(name).expect("Unknown preset"))
+        .collect();
+
+    // every preset describes a distinct interpreter
+    for (i, quirks) in presets.iter().enumerate() {
+        assert!(!presets[i + 1..].contains(quirks));
+    }
+}
+
+#[test]
+fn test_quirk_shift_uses_vy() {
+    for (instruction, shifted_vy, shifted_vx, carry_vy, carry_vx) in
+        &[(0x8126, 0x41, 0x08, 1, 0), (0x812E, 0x06, 0x20, 1, 0)]
+    {
+        for (quirks, expected, carry) in &[
+            (Quirks::cosmac_vip(), shifted_vy, carry_vy),
+            (Quirks::xo_chip(), shifted_vy, carry_vy),
+            (Quirks::modern(), shifted_vx, carry_vx),
+        ] {
+            do_cycle_with_quirks(
+                *instruction,
+                *quirks,
+                |state| {
+                    state.registers[1] = 0x10;
+                    state.registers[2] = 0x83;
+                },
+                |state| {
+                    assert_eq!(state.registers[1], **expected);
+                    assert_eq!(state.registers[2], 0x83);
+                    assert_eq!(state.registers[0xF], **carry);
+                    assert_eq!(state.program_counter, 0x202);
+                },
+            );
+        }
+    }
+}
+
+#[test]
+fn test_quirk_load_store_index_increment() {
+    for instruction in &[0xF355, 0xF365] {
+        for (quirks, expected_index) in &[
+            (Quirks::cosmac_vip(), 0x404),
+            (Quirks::chip48(), 0x403),
+            (Quirks::modern(), 0x400),
+        ] {
+            do_cycle_with_quirks(
+                *instruction,
+                *quirks,
+                |state| {
+                    state.index = 0x400;
+                },
+                |state| {
+                    assert_eq!(state.index, *expected_index);
+                    assert_eq!(state.program_counter, 0x202);
+                },
+            );
+        }
+    }
+
+    // CHIP-48 only differs from SUPER-CHIP in this quirk
+    assert_ne!(Quirks::chip48(), Quirks::super_chip());
+}
+
+#[test]
+fn test_quirk_jump_uses_vx() {
+    for (quirks, expected_pc) in &[(Quirks::chip48(), 0x2AF), (Quirks::modern(), 0x2AB)] {
+        do_cycle_with_quirks(
+            0xB2AA,
+            *quirks,
+            |state| {
+                state.registers[0] = 0x1;
+                state.registers[2] = 0x5;
+            },
+            |state| {
+                assert_eq!(state.program_counter, *expected_pc);
+            },
+        );
+    }
+}
+
+#[test]
+fn test_quirk_logic_resets_vf() {
+    for instruction in &[0x8121, 0x8122, 0x8123] {
+        for (quirks, expected_vf) in &[(Quirks::cosmac_vip(), 0x0), (Quirks::modern(), 0xAB)] {
+            do_cycle_with_quirks(
+                *instruction,
+                *quirks,
+                |state| {
+                    state.registers[1] = 0x0F;
+                    state.registers[2] = 0x3C;
+                    state.registers[0xF] = 0xAB;
+                },
+                |state| {
+                    assert_eq!(state.registers[0xF], *expected_vf);
+                    assert_eq!(state.program_counter, 0x202);
+                },
+            );
+        }
+    }
+}
+
+#[test]
+fn test_quirk_clip_sprites() {
+    for (quirks, wrapped) in &[(Quirks::cosmac_vip(), false), (Quirks::modern(), true)] {
+        do_cycle_with_quirks(
+            0xD014,
+            *quirks,
+            |state| {
+                // the start coordinate always wraps around
+                state.registers[0] = 60 + 64;
+                state.registers[1] = 30 + 32;
+                state.index = 0x400;
+                for i in 0..4 {
+                    state.memory[0x400 + i] = 0xFF;
+                }
+            },
+            |state| {
+                for x in 0..64 {
+                    for y in 0..32 {
+                        let inside = x >= 60 && y >= 30;
+                        let wrapped_around =
+                            !(4..60).contains(&x) && !(2..30).contains(&y) && !inside;
+                        assert_eq!(
+                            state.output_pin(0, x, y),
+                            inside || (*wrapped && wrapped_around)
+                        );
+                    }
+                }
+                assert_eq!(state.program_counter, 0x202);
+            },
+        );
+    }
+}
+
+#[test]
+fn test_resolution_switch() {
+    do_cycle(
+        0x00FF,
+        |state| {
+            set_output_pin(state, 0, 0, 0);
+        },
+        |state| {
+            let framebuffer = state.framebuffer();
+            assert_eq!((framebuffer.width(), framebuffer.height()), (128, 64));
+            assert!(framebuffer.pixels().iter().all(|pixel| *pixel == 0));
+            assert_eq!(state.program_counter, 0x202);
+        },
+    );
+
+    do_cycle(
+        0x00FE,
+        |state| {
+            state.hires = true;
+            set_output_pin(state, 0, 0, 0);
+        },
+        |state| {
+            let framebuffer = state.framebuffer();
+            assert_eq!((framebuffer.width(), framebuffer.height()), (64, 32));
+            assert!(framebuffer.pixels().iter().all(|pixel| *pixel == 0));
+            assert_eq!(state.program_counter, 0x202);
+        },
+    );
+}
+
+#[test]
+fn test_scroll() {
+    for hires in &[false, true] {
+        for (instruction, dx, dy) in &[(0x00C3, 0, 3), (0x00FB, 4, 0), (0x00FC, -4, 0)] {
+            do_cycle(
+                *instruction,
+                |state| {
+                    state.hires = *hires;
+                    set_output_pin(state, 0, 10, 5);
+                    set_output_pin(state, 0, 0, 0);
+                },
+                |state| {
+                    let x = (10 + dx) as usize;
+                    let y = (5 + dy) as usize;
+                    let expected_pins = if *dx < 0 { 1 } else { 2 };
+                    assert!(state.output_pin(0, x, y));
+                    assert_eq!(
+                        state
+                            .framebuffer()
+                            .pixels()
+                            .iter()
+                            .filter(|pixel| **pixel != 0)
+                            .count(),
+                        expected_pins
+                    );
+                    assert_eq!(state.program_counter, 0x202);
+                },
+            );
+        }
+    }
+}
+
+#[test]
+fn test_draw_large_sprite() {
+    do_cycle(
+        0xD010,
+        |state| {
+            state.hires = true;
+            state.registers[0] = 100;
+            state.registers[1] = 40;
+            state.index = 0x400;
+            for row in 0..16 {
+                state.memory[0x400 + 2 * row] = 0x80;
+                state.memory[0x400 + 2 * row + 1] = 0x01;
+            }
+        },
+        |state| {
+            for y in 0..64 {
+                for x in 0..128 {
+                    let expected = (40..56).contains(&y) && (x == 100 || x == 115);
+                    assert_eq!(state.output_pin(0, x, y), expected);
+                }
+            }
+            assert_eq!(state.registers[0xF], 0);
+            assert_eq!(state.program_counter, 0x202);
+        },
+    );
+}
+
+#[test]
+fn test_draw_sprite_collision() {
+    // a large sprite wrapping around the right edge is erased by drawing it twice
+    let mut state = prepare_state_with_program(&[0x00, 0xFF, 0xD0, 0x10, 0xD0, 0x10]);
+    state.quirks.clip_sprites = false;
+    state.registers[0] = 120;
+    state.index = 0x400;
+    for row in 0..16 {
+        state.memory[0x400 + 2 * row] = 0xFF;
+        state.memory[0x400 + 2 * row + 1] = 0x81;
+    }
+    state.cycle().expect("Cycle failed");
+    state.cycle().expect("Cycle failed");
+    for x in 0..128 {
+        assert_eq!(state.output_pin(0, x, 0), x >= 120 || x == 0 || x == 7);
+    }
+    assert_eq!(state.registers[0xF], 0);
+
+    state.cycle().expect("Cycle failed");
+    assert!(state.framebuffer().pixels().iter().all(|pixel| *pixel == 0));
+    assert_eq!(state.registers[0xF], 1);
+}
+
+#[test]
+fn test_load_big_sprite() {
+    for reg in 0x0..=0xF {
+        for character in 0x0..=0xF {
+            let instruction = 0xF030 as u16 | (reg << 8) as u16;
+            do_cycle(
+                instruction,
+                |state| {
+                    state.registers[reg] = character;
+                },
+                |state| {
+                    assert_eq!(state.program_counter, 0x202);
+                    assert_eq!(
+                        state.index,
+                        SCHIP_BIG_CHARSET_OFFSET + character as u16 * 10
+                    );
+                },
+            );
+        }
+    }
+}
+
+#[test]
+fn test_rpl_flags_save_load() {
+    let mut register_values = [0; 16];
+    let mut rng = thread_rng();
+    for i in 0x0..=0xF {
+        register_values[i] = rng.gen_range(0, 255);
+    }
+    for reg in 0x0..=0xF {
+        let instruction = 0xF075 | (reg << 8) as u16;
+        do_cycle(
+            instruction,
+            |state| {
+                state.registers = register_values;
+            },
+            |state| {
+                for r in 0x0..=0xF {
+                    let expected = if r <= reg { register_values[r] } else { 0 };
+                    assert_eq!(state.rpl_flags[r], expected);
+                }
+                assert_eq!(state.program_counter, 0x202);
+            },
+        );
+
+        let instruction = 0xF085 | (reg << 8) as u16;
+        do_cycle(
+            instruction,
+            |state| {
+                state.rpl_flags = register_values;
+            },
+            |state| {
+                for r in 0x0..=0xF {
+                    let expected = if r <= reg { register_values[r] } else { 0 };
+                    assert_eq!(state.registers[r], expected);
+                }
+                assert_eq!(state.program_counter, 0x202);
+            },
+        );
+    }
+}
+
+#[test]
+fn test_exit() {
+    do_cycle(
+        0x00FD,
+        |state| {
+            assert!(!state.has_exited());
+        },
+        |state| {
+            assert!(state.has_exited());
+            assert_eq!(state.program_counter, 0x200);
+            state.delay_timer = 0xAB;
+            for _ in 0..100 {
+                state.cycle().expect("Cycle failed");
+            }
+            assert_eq!(state.delay_timer, 0xAB);
+            assert_eq!(state.program_counter, 0x200);
+        },
+    );
+}
+
+/// Prepares a new XO-CHIP with the given program loaded.
+fn prepare_xo_chip_state(program: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::with_variant(Variant::XoChip, Quirks::xo_chip());
+    chip8.load_program(program).expect("Loading program failed");
+    chip8
+}
+
+#[test]
+fn test_xo_chip_memory_size() {
+    let mut state = Chip8::with_variant(Variant::XoChip, Quirks::xo_chip());
+    assert_eq!(state.memory.len(), 65536);
+    assert!(state.load_program(&vec![0; 65536 - 0x200]).is_ok());
+    assert_eq!(
+        state.load_program(&vec![0; 65536 - 0x200 + 1]).err(),
+        Some(LoadProgramError::ProgramTooLarge(
+            65536 - 0x200 + 1,
+            65536 - 0x200
+        ))
+    );
+
+    let mut state = Chip8::default();
+    assert_eq!(state.memory.len(), 4096);
+    assert_eq!(
+        state.load_program(&vec![0; 4096 - 0x200 + 1]).err(),
+        Some(LoadProgramError::ProgramTooLarge(
+            4096 - 0x200 + 1,
+            4096 - 0x200
+        ))
+    );
+}
+
+#[test]
+fn test_long_index_load() {
+    let mut state = prepare_xo_chip_state(&[0xF0, 0x00, 0xBE, 0xEF]);
+    state.cycle().expect("Cycle failed");
+    assert_eq!(state.index, 0xBEEF);
+    assert_eq!(state.program_counter, 0x204);
+
+    do_failing_cycle(
+        0xF000,
+        |_| {},
+        ExecutionError::UnknownOpcode(0x200, [0xF0, 0x00]),
+    );
+}
+
+#[test]
+fn test_skip_long_instruction() {
+    let mut state = prepare_xo_chip_state(&[0x30, 0x00, 0xF0, 0x00, 0xBE, 0xEF]);
+    state.cycle().expect("Cycle failed");
+    assert_eq!(state.program_counter, 0x206);
+
+    let mut state = prepare_xo_chip_state(&[0x30, 0x01, 0xF0, 0x00, 0xBE, 0xEF]);
+    state.cycle().expect("Cycle failed");
+    assert_eq!(state.program_counter, 0x202);
+}
+
+#[test]
+fn test_register_range_save_load() {
+    for (instruction, order) in &[(0x5242_u16, [2, 3, 4]), (0x5422, [4, 3, 2])] {
+        let mut state =
+            prepare_xo_chip_state(&[(instruction >> 8) as u8, (instruction & 0xFF) as u8]);
+        state.registers[2] = 0x12;
+        state.registers[3] = 0x34;
+        state.registers[4] = 0x56;
+        state.index = 0x400;
+        state.cycle().expect("Cycle failed");
+        for (offset, reg) in order.iter().enumerate() {
+            assert_eq!(state.memory[0x400 + offset], state.registers[*reg]);
+        }
+        assert_eq!(state.memory[0x403], 0);
+        assert_eq!(state.index, 0x400);
+        assert_eq!(state.program_counter, 0x202);
+
+        let instruction = instruction | 0x1;
+        let mut state =
+            prepare_xo_chip_state(&[(instruction >> 8) as u8, (instruction & 0xFF) as u8]);
+        state.index = 0x400;
+        state.memory[0x400..0x403].copy_from_slice(&[0x12, 0x34, 0x56]);
+        state.cycle().expect("Cycle failed");
+        for (offset, reg) in order.iter().enumerate() {
+            assert_eq!(state.registers[*reg], state.memory[0x400 + offset]);
+        }
+        assert_eq!(state.index, 0x400);
+        assert_eq!(state.program_counter, 0x202);
+    }
+
+    do_failing_cycle(
+        0x5122,
+        |_| {},
+        ExecutionError::UnknownOpcode(0x200, [0x51, 0x22]),
+    );
+}
+
+#[test]
+fn test_plane_selection() {
+    // select both planes and draw a sprite whose rows differ per plane
+    let mut state = prepare_xo_chip_state(&[0xF3, 0x01, 0xD0, 0x01]);
+    state.index = 0x400;
+    state.memory[0x400] = 0xF0;
+    state.memory[0x401] = 0x3C;
+    state.cycle().expect("Cycle failed");
+    assert_eq!(state.selected_planes, 0x3);
+    state.cycle().expect("Cycle failed");
+
+    for x in 0..8 {
+        assert_eq!(state.output_pin(0, x, 0), x < 4);
+        assert_eq!(state.output_pin(1, x, 0), (2..6).contains(&x));
+    }
+
+    // clearing only affects the selected planes
+    let mut state = prepare_xo_chip_state(&[0xF2, 0x01, 0x00, 0xE0]);
+    set_output_pin(&mut state, 0, 0, 0);
+    set_output_pin(&mut state, 1, 0, 0);
+    state.cycle().expect("Cycle failed");
+    state.cycle().expect("Cycle failed");
+    assert!(state.output_pin(0, 0, 0));
+    assert!(!state.output_pin(1, 0, 0));
+
+    do_failing_cycle(
+        0xF301,
+        |_| {},
+        ExecutionError::UnknownOpcode(0x200, [0xF3, 0x01]),
+    );
+}
+
+#[test]
+fn test_audio_pattern_and_pitch() {
+    let mut state = prepare_xo_chip_state(&[0xF0, 0x02, 0xF5, 0x3A]);
+    state.index = 0x400;
+    for i in 0..16 {
+        state.memory[0x400 + i] = i as u8;
+    }
+    state.registers[5] = 0xAB;
+    state.cycle().expect("Cycle failed");
+    state.cycle().expect("Cycle failed");
+    for i in 0..16 {
+        assert_eq!(state.audio_pattern[i], i as u8);
+    }
+    assert_eq!(state.pitch, 0xAB);
+    assert_eq!(state.program_counter, 0x204);
+}
+
+#[test]
+fn test_scroll_up() {
+    let mut state = prepare_xo_chip_state(&[0x00, 0xD2]);
+    set_output_pin(&mut state, 0, 10, 5);
+    state.cycle().expect("Cycle failed");
+    assert!(state.output_pin(0, 10, 3));
+    assert_eq!(
+        state
+            .framebuffer()
+            .pixels()
+            .iter()
+            .filter(|pixel| **pixel != 0)
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn test_disassemble() {
+    let program = [
+        0x00, 0xE0, 0x6A, 0x1F, 0xA2, 0xA4, 0xD0, 0x15, 0x8A, 0xB4, 0xF3, 0x29, 0xF0, 0x00, 0xBE,
+        0xEF, 0x12, 0xA4, 0x00, 0x00, 0xAB,
+    ];
+    let instructions = disassemble(&program, 0x200);
+    let listing: Vec<(u16, Option<&str>)> = instructions
+        .iter()
+        .map(|instruction| (instruction.address, instruction.mnemonic.as_deref()))
+        .collect();
+    assert_eq!(
+        listing,
+        vec![
+            (0x200, Some("CLS")),
+            (0x202, Some("LD VA, 0x1F")),
+            (0x204, Some("LD I, 0x2A4")),
+            (0x206, Some("DRW V0, V1, 5")),
+            (0x208, Some("ADD VA, VB")),
+            (0x20A, Some("LD F, V3")),
+            (0x20C, Some("LD I, LONG 0xBEEF")),
+            (0x210, Some("JP 0x2A4")),
+            (0x212, None),
+            (0x214, None),
+        ]
+    );
+    assert_eq!(instructions[6].bytes, vec![0xF0, 0x00, 0xBE, 0xEF]);
+    assert_eq!(instructions[9].bytes, vec![0xAB]);
+}
+
+#[test]
+fn test_disassemble_all_opcodes() {
+    // every opcode the emulator executes without error must have a mnemonic
+    for word in 0x0000..=0xFFFF_u16 {
+        if word == 0xF000 {
+            continue;
+        }
+        let bytes = [(word >> 8) as u8, (word & 0xFF) as u8];
+        let mut state = Chip8::with_variant(Variant::XoChip, Quirks::default());
+        state.load_program(&bytes).expect("Loading program failed");
+        state.registers[0x0] = 0x1;
+        state.input_pins[0x0] = true;
+        state.stack_pointer = 1;
+        let executed = state.cycle().is_ok();
+        let mnemonic = disassemble(&bytes, 0x200)[0].mnemonic.clone();
+        assert_eq!(executed, mnemonic.is_some(), "{:04X}", word);
+    }
+}
+
+#[test]
+fn test_reachable_addresses() {
+    let program = [
+        0x22, 0x0A, // 0x200: CALL 0x20A
+        0x30, 0x01, // 0x202: SE V0, 0x01
+        0x12, 0x08, // 0x204: JP 0x208
+        0xAB, 0xCD, // 0x206: data
+        0x12, 0x08, // 0x208: JP 0x208
+        0x00, 0xEE, // 0x20A: RET
+        0x00, 0xE0, // 0x20C: unreachable
+    ];
+    let reachable: Vec<u16> = reachable_addresses(&program, 0x200).into_iter().collect();
+    assert_eq!(reachable, vec![0x200, 0x202, 0x204, 0x206, 0x208, 0x20A]);
+
+    let program = [
+        0x12, 0x04, // 0x200: JP 0x204
+        0xFF, 0xFF, // 0x202: data
+        0x00, 0xFD, // 0x204: EXIT
+        0x00, 0xE0, // 0x206: unreachable
+    ];
+    let reachable: Vec<u16> = reachable_addresses(&program, 0x200).into_iter().collect();
+    assert_eq!(reachable, vec![0x200, 0x204]);
+}
+
+#[test]
+fn test_assemble() {
+    let source = "
+        SPRITE_HEIGHT EQU 5
+        X EQU 0x0A
+
+        start:  CLS                 ; clear the screen
+                LD VA, X
+                LD I, sprite
+                DRW V0, V1, SPRITE_HEIGHT
+                LD I, LONG 0xBEEF
+        loop:   JP loop
+        sprite: DB 0xF0, 0b10010000, 144 ; partial zero
+                DW 0x90F0, sprite + 2 - start
+    ";
+    let program = assemble(source, 0x200).expect("Assembling failed");
+    assert_eq!(
+        program,
+        vec![
+            0x00, 0xE0, 0x6A, 0x0A, 0xA2, 0x0E, 0xD0, 0x15, 0xF0, 0x00, 0xBE, 0xEF, 0x12, 0x0C,
+            0xF0, 0x90, 0x90, 0x90, 0xF0, 0x00, 0x10,
+        ]
+    );
+}
+
+#[test]
+fn test_assemble_all_opcodes_round_trip() {
+    // every mnemonic produced by the disassembler must assemble to the original bytes
+    for word in 0x0000..=0xFFFF_u16 {
+        let bytes = if word == 0xF000 {
+            vec![0xF0, 0x00, 0x12, 0x34]
+        } else {
+            vec![(word >> 8) as u8, (word & 0xFF) as u8]
+        };
+        if let Some(mnemonic) = &disassemble(&bytes, 0x200)[0].mnemonic {
+            assert_eq!(assemble(mnemonic, 0x200), Ok(bytes), "{}", mnemonic);
+        }
+    }
+}
+
+#[test]
+fn test_assemble_disassemble_round_trip() {
+    let program: Vec<u8> = (0..=0xFF_u8).cycle().step_by(7).take(512).collect();
+    let source: Vec<String> = disassemble(&program, 0x200)
+        .into_iter()
+        .map(|instruction| match instruction.mnemonic {
+            Some(mnemonic) => mnemonic,
+            None => {
+                let bytes: Vec<String> = instruction
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:#04X}", byte))
+                    .collect();
+                format!("DB {}", bytes.join(", "))
+            }
+        })
+        .collect();
+    assert_eq!(assemble(&source.join("\n"), 0x200), Ok(program));
+}
+
+#[test]
+fn test_assemble_include() {
+    let directory = std::env::temp_dir().join(format!("chip8-asm-test-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).expect("Creating directory failed");
+    std::fs::write(
+        directory.join("main.asm"),
+        "JP start\ninclude \"sprites.asm\"\nstart: LD I, digit",
+    )
+    .expect("Writing file failed");
+    std::fs::write(directory.join("sprites.asm"), "digit: DB 0x20, 0x60")
+        .expect("Writing file failed");
+    std::fs::write(directory.join("cyclic.asm"), "include \"cyclic.asm\"")
+        .expect("Writing file failed");
+
+    let program = assemble_file(&directory.join("main.asm"), 0x200);
+    let cyclic = assemble_file(&directory.join("cyclic.asm"), 0x200);
+    std::fs::remove_dir_all(&directory).expect("Removing directory failed");
+
+    assert_eq!(program, Ok(vec![0x12, 0x04, 0x20, 0x60, 0xA2, 0x02]));
+    assert!(matches!(
+        cyclic,
+        Err(AssemblerError::IncludeDepthExceeded(_))
+    ));
+}
+
+#[test]
+fn test_assemble_errors() {
+    let location = |line| Location {
+        file: "<input>".to_string(),
+        line,
+    };
+    assert_eq!(
+        assemble("CLS\nFOO V0", 0x200),
+        Err(AssemblerError::UnknownMnemonic(
+            location(2),
+            "FOO".to_string()
+        ))
+    );
+    assert_eq!(
+        assemble("DRW V0, V1", 0x200),
+        Err(AssemblerError::InvalidOperands(
+            location(1),
+            "DRW".to_string()
+        ))
+    );
+    assert_eq!(
+        assemble("JP nowhere", 0x200),
+        Err(AssemblerError::UnknownSymbol(
+            location(1),
+            "nowhere".to_string()
+        ))
+    );
+    assert_eq!(
+        assemble("a: CLS\na: CLS", 0x200),
+        Err(AssemblerError::DuplicateSymbol(
+            location(2),
+            "a".to_string()
+        ))
+    );
+    assert_eq!(
+        assemble("LD V0, 0x100", 0x200),
+        Err(AssemblerError::ValueOutOfRange(location(1), 0x100))
+    );
+    assert_eq!(
+        assemble("LD V0, 0xZZ", 0x200),
+        Err(AssemblerError::InvalidExpression(
+            location(1),
+            "0xZZ".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_save_and_load_state() {
+    let mut state = prepare_xo_chip_state(&[0xF0, 0x00, 0x12, 0x34, 0x00, 0xFF, 0x22, 0x00]);
+    state.registers[0x3] = 0xAB;
+    state.delay_timer = 0x20;
+    state.set_input_pin(0x7, true);
+    for _ in 0..3 {
+        state.cycle().expect("Cycle failed");
+    }
+    set_output_pin(&mut state, 1, 42, 0);
+    let snapshot = state.save_state();
+
+    let mut restored = Chip8::default();
+    restored
+        .load_state(&snapshot)
+        .expect("Loading state failed");
+    assert_eq!(restored.save_state(), snapshot);
+    assert_eq!(restored.variant(), Variant::XoChip);
+    assert_eq!(restored.quirks, Quirks::xo_chip());
+    assert_eq!(restored.index, 0x1234);
+    assert_eq!(restored.program_counter, 0x200);
+    assert_eq!(restored.stack[0], 0x206);
+    assert_eq!(restored.stack_pointer, 1);
+    assert_eq!(restored.registers[0x3], 0xAB);
+    assert_eq!(restored.delay_timer, 0x20);
+    assert!(restored.hires);
+    assert!(restored.input_pins[0x7]);
+    assert!(restored.output_pin(1, 42, 0));
+    assert_eq!(restored.memory, state.memory);
+
+    // both chips continue identically
+    for _ in 0..10 {
+        state.cycle().expect("Cycle failed");
+        restored.cycle().expect("Cycle failed");
+    }
+    assert_eq!(restored.save_state(), state.save_state());
+
+    // the quirks of all presets survive a round trip
+    for quirks in &[
+        Quirks::cosmac_vip(),
+        Quirks::chip48(),
+        Quirks::super_chip(),
+        Quirks::modern(),
+    ] {
+        restored
+            .load_state(&Chip8::new(*quirks).save_state())
+            .expect("Loading state failed");
+        assert_eq!(restored.quirks, *quirks);
+    }
+}
+
+#[test]
+fn test_load_invalid_state() {
+    let mut state = Chip8::default();
+    let snapshot = state.save_state();
+    state.registers[0x0] = 0x12;
+
+    let mut corrupted = snapshot.clone();
+    corrupted[100] ^= 0x1;
+    assert_eq!(
+        state.load_state(&corrupted),
+        Err(LoadStateError::ChecksumMismatch)
+    );
+
+    let mut newer = snapshot.clone();
+    newer[5] += 1;
+    assert_eq!(
+        state.load_state(&newer),
+        Err(LoadStateError::UnsupportedVersion(SAVE_STATE_VERSION + 1))
+    );
+
+    assert_eq!(
+        state.load_state(&snapshot[..snapshot.len() - 1]),
+        Err(LoadStateError::InvalidFormat)
+    );
+    assert_eq!(
+        state.load_state(b"garbage"),
+        Err(LoadStateError::InvalidFormat)
+    );
+
+    // the state is left untouched by failed attempts
+    assert_eq!(state.registers[0x0], 0x12);
+}
+
+#[test]
+fn test_breakpoints() {
+    // 0x200: LD V0, 0x01; 0x202: ADD V0, 0x01; 0x204: DRW V0, V0, 1; 0x206: JP 0x202
+    let mut state = prepare_xo_chip_state(&[0x60, 0x01, 0x70, 0x01, 0xD0, 0x01, 0x12, 0x02]);
+    state.add_breakpoint(Breakpoint::Address(0x202));
+    state.add_breakpoint("DXYN".parse().expect("Parsing breakpoint failed"));
+
+    state.cycle().expect("Cycle failed");
+    assert_eq!(state.take_stop_reason(), None);
+
+    // the instruction at a breakpoint is not executed until the execution resumes
+    state.cycle().expect("Cycle failed");
+    assert_eq!(
+        state.take_stop_reason(),
+        Some(StopReason::Breakpoint(Breakpoint::Address(0x202), 0x202))
+    );
+    assert_eq!(state.registers[0x0], 0x01);
+    assert_eq!(state.program_counter, 0x202);
+    state.cycle().expect("Cycle failed");
+    assert_eq!(state.take_stop_reason(), None);
+    assert_eq!(state.registers[0x0], 0x02);
+
+    state.cycle().expect("Cycle failed");
+    assert_eq!(
+        state.take_stop_reason(),
+        Some(StopReason::Breakpoint(
+            Breakpoint::Opcode(0xD000, 0xF000),
+            0x204
+        ))
+    );
+
+    assert!(state.remove_breakpoint(Breakpoint::Address(0x202)));
+    assert!(!state.remove_breakpoint(Breakpoint::Address(0x202)));
+    for _ in 0..3 {
+        state.cycle().expect("Cycle failed");
+    }
+    assert_eq!(state.take_stop_reason(), None);
+    assert_eq!(state.registers[0x0], 0x03);
+}
+
+#[test]
+fn test_watchpoints() {
+    // 0x200: LD I, 0x300; 0x202: LD [I], V1; 0x204: LD V1, [I]; 0x206: ADD V2, 0x01
+    let mut state = prepare_xo_chip_state(&[0xA3, 0x00, 0xF1, 0x55, 0xF1, 0x65, 0x72, 0x01]);
+    state.registers[0x1] = 0xAB;
+    state.add_watchpoint(
+        "write:0x301-0x30F"
+            .parse()
+            .expect("Parsing watchpoint failed"),
+    );
+    state.add_watchpoint(Watchpoint::Read(0x302, 0x303));
+    state.add_watchpoint("V2".parse().expect("Parsing watchpoint failed"));
+
+    state.cycle().expect("Cycle failed");
+    assert_eq!(state.take_stop_reason(), None);
+
+    // watchpoints stop the execution after the instruction was executed
+    state.cycle().expect("Cycle failed");
+    assert_eq!(
+        state.take_stop_reason(),
+        Some(StopReason::MemoryWrite(
+            Watchpoint::Write(0x301, 0x30F),
+            0x202,
+            0x301
+        ))
+    );
+    assert_eq!(state.memory[0x301], 0xAB);
+
+    // the XO-CHIP quirks increment the index register when storing registers
+    state.cycle().expect("Cycle failed");
+    assert_eq!(
+        state.take_stop_reason(),
+        Some(StopReason::MemoryRead(
+            Watchpoint::Read(0x302, 0x303),
+            0x204,
+            0x302
+        ))
+    );
+
+    state.cycle().expect("Cycle failed");
+    assert_eq!(
+        state.take_stop_reason(),
+        Some(StopReason::RegisterChanged(
+            Watchpoint::Register(0x2),
+            0x206,
+            0x00,
+            0x01
+        ))
+    );
+}
+
+#[test]
+fn test_parse_breakpoints_and_watchpoints() {
+    for (s, breakpoint) in &[
+        ("0x2A4", Breakpoint::Address(0x2A4)),
+        ("DXYN", Breakpoint::Opcode(0xD000, 0xF000)),
+        ("00E0", Breakpoint::Opcode(0x00E0, 0xFFFF)),
+        ("F_55", Breakpoint::Opcode(0xF055, 0xF0FF)),
+    ] {
+        assert_eq!(s.parse(), Ok(*breakpoint));
+        assert_eq!(breakpoint.to_string().parse(), Ok(*breakpoint));
+    }
+    for s in &["", "0x", "D", "DXY", "DXYN0", "D#YN"] {
+        assert_eq!(
+            s.parse::<Breakpoint>(),
+            Err(ParseError::InvalidBreakpoint(s.to_string()))
+        );
+    }
+
+    for (s, watchpoint) in &[
+        ("read:0x300", Watchpoint::Read(0x300, 0x300)),
+        ("write:0x300-0x30F", Watchpoint::Write(0x300, 0x30F)),
+        ("VA", Watchpoint::Register(0xA)),
+    ] {
+        assert_eq!(s.parse(), Ok(*watchpoint));
+        assert_eq!(watchpoint.to_string().parse(), Ok(*watchpoint));
+    }
+    for s in &["", "read:", "write:0x30F-0x300", "VG", "V10", "0x300"] {
+        assert_eq!(
+            s.parse::<Watchpoint>(),
+            Err(ParseError::InvalidWatchpoint(s.to_string()))
+        );
+    }
+}
+
+#[test]
+fn test_breakpoints_survive_load_state() {
+    let mut state = Chip8::default();
+    let snapshot = state.save_state();
+    state.add_breakpoint(Breakpoint::Address(0x200));
+    state.load_state(&snapshot).expect("Loading state failed");
+    assert_eq!(state.breakpoints(), &[Breakpoint::Address(0x200)]);
+}
+
+/// Formats the display of `chip8` as a frame, with one line per row where `#` is a
+/// set pixel and `.` is a cleared pixel.
+fn format_frame(chip8: &Chip8) -> String {
+    let framebuffer = chip8.framebuffer();
+    framebuffer
+        .pixels()
+        .chunks(framebuffer.width())
+        .map(|row| {
+            row.iter()
+                .map(|pixel| if *pixel != 0 { '#' } else { '.' })
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Compares two frames and returns a readable diff if they differ. In the diff,
+/// `+` marks pixels that are set but expected to be cleared, and `-` marks pixels
+/// that are cleared but expected to be set.
+fn frame_diff(expected: &str, actual: &str) -> Option<String> {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    if expected.len() != actual.len()
+        || expected
+            .iter()
+            .zip(&actual)
+            .any(|(e, a)| e.len() != a.len())
+    {
+        return Some(format!(
+            "Expected a {}x{} frame, got {}x{}",
+            expected.first().map_or(0, |row| row.len()),
+            expected.len(),
+            actual.first().map_or(0, |row| row.len()),
+            actual.len()
+        ));
+    }
+
+    let mut mismatches = 0;
+    let diff: Vec<String> = expected
+        .iter()
+        .zip(&actual)
+        .map(|(expected_row, actual_row)| {
+            expected_row
+                .chars()
+                .zip(actual_row.chars())
+                .map(|pixels| match pixels {
+                    ('.', '#') => {
+                        mismatches += 1;
+                        '+'
+                    }
+                    ('#', '.') => {
+                        mismatches += 1;
+                        '-'
+                    }
+                    (_, actual) => actual,
+                })
+                .collect()
+        })
+        .collect();
+    match mismatches {
+        0 => None,
+        _ => Some(format!(
+            "{} pixels differ (+ unexpectedly set, - unexpectedly cleared):\n{}",
+            mismatches,
+            diff.join("\n")
+        )),
+    }
+}
+
+/// Loads the program at `program_path` into `chip8`, runs it for `cycles` cycles
+/// using the default scheduler and compares the display to the golden frame at
+/// `golden_path`. Both paths are relative to the crate root. If the environment
+/// variable `UPDATE_GOLDEN` is set, the golden frame is overwritten with the
+/// display instead.
+fn assert_golden_frame(mut chip8: Chip8, program_path: &str, cycles: usize, golden_path: &str) {
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    let program = std::fs::read(root.join(program_path)).expect("Reading program failed");
+    chip8
+        .load_program(&program)
+        .expect("Loading program failed");
+    let mut scheduler = Scheduler::default();
+    for _ in 0..cycles {
+        scheduler.cycle(&mut chip8).expect("Cycle failed");
+    }
+
+    let actual = format_frame(&chip8);
+    let golden_path = root.join(golden_path);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&golden_path, actual + "\n").expect("Writing golden frame failed");
+        return;
+    }
+    let expected = std::fs::read_to_string(&golden_path).expect("Reading golden frame failed");
+    if let Some(diff) = frame_diff(&expected, &actual) {
+        panic!(
+            "Display of {} does not match golden frame\n{}",
+            program_path, diff
+        );
+    }
+}
+
+#[test]
+fn test_frame_diff() {
+    assert_eq!(frame_diff("#.\n.#\n", "#.\n.#"), None);
+    assert_eq!(
+        frame_diff("#.\n.#", "##\n.."),
+        Some("2 pixels differ (+ unexpectedly set, - unexpectedly cleared):\n#+\n.-".to_string())
+    );
+    assert_eq!(
+        frame_diff("#.\n.#", "#."),
+        Some("Expected a 2x2 frame, got 2x1".to_string())
+    );
+}
+
+#[test]
+fn test_golden_hello_world() {
+    assert_golden_frame(
+        Chip8::default(),
+        "programs/hello-world.ch8",
+        2000,
+        "programs/golden/hello-world.txt",
+    );
+}
+
+/// A random source that always returns the same byte.
+struct ConstantRandom(u8);
+
+impl RandomSource for ConstantRandom {
+    fn next_byte(&mut self) -> u8 {
+        self.0
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        vec![self.0]
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), LoadStateError> {
+        match state {
+            [byte] => {
+                self.0 = *byte;
+                Ok(())
+            }
+            _ => Err(LoadStateError::InvalidFormat),
+        }
+    }
+}
+
+#[test]
+fn test_rnd() {
+    // RND V0, 0xFF; JP 0x200
+    let program = [0xC0, 0xFF, 0x12, 0x00];
+    let samples = |seed| {
+        let mut state = Chip8::with_seed(Variant::Chip8, Quirks::default(), seed);
+        state
+            .load_program(&program)
+            .expect("Loading program failed");
+        (0..2000)
+            .map(|_| {
+                state.cycle().expect("Cycle failed");
+                state.cycle().expect("Cycle failed");
+                state.registers[0x0]
+            })
+            .collect::<Vec<u8>>()
+    };
+    let first = samples(42);
+    assert_eq!(first, samples(42));
+    assert_ne!(first, samples(43));
+    assert!(first.contains(&0x00));
+    assert!(first.contains(&0xFF));
+
+    do_cycle(
+        0xC10F,
+        |state| state.set_random_source(Box::new(ConstantRandom(0xAB))),
+        |state| assert_eq!(state.registers[0x1], 0x0B),
+    );
+}
+
+#[test]
+fn test_rnd_survives_load_state() {
+    // RND V0, 0xFF; JP 0x200
+    let mut state = Chip8::with_seed(Variant::Chip8, Quirks::default(), 7);
+    state
+        .load_program(&[0xC0, 0xFF, 0x12, 0x00])
+        .expect("Loading program failed");
+    let snapshot = state.save_state();
+
+    let mut samples = vec![];
+    for _ in 0..20 {
+        state.cycle().expect("Cycle failed");
+        samples.push(state.registers[0x0]);
+    }
+    state.load_state(&snapshot).expect("Loading state failed");
+    for sample in samples {
+        state.cycle().expect("Cycle failed");
+        assert_eq!(state.registers[0x0], sample);
+    }
+
+    // the state of a replaced random source is restored as well
+    state.set_random_source(Box::new(ConstantRandom(0x12)));
+    let snapshot = state.save_state();
+    state.set_random_source(Box::new(ConstantRandom(0x34)));
+    state.load_state(&snapshot).expect("Loading state failed");
+    assert_eq!(state.random.next_byte(), 0x12);
+
+    // states of a different random source are rejected
+    let mut other = Chip8::default();
+    assert_eq!(
+        other.load_state(&snapshot),
+        Err(LoadStateError::InvalidFormat)
+    );
+}
@@ -1,19 +1,123 @@
-use crate::chip::chip8::Chip8;
+use crate::chip::{
+    chip8::{constants::MAX_DIRTY_RECTS, variant::Variant, Chip8},
+    framebuffer::Rect,
+    ExecutionError,
+};
 
 /// Convenience function to do a conditional skip in case `f(opcode, state)` evaluates to
 /// `true`.
 pub(crate) fn conditional_skip<T>(opcode: &T, state: &mut Chip8, f: fn(&T, &Chip8) -> bool) {
     if f(opcode, state) {
+        skip_next_instruction(state);
+    }
+}
+
+/// Convenience function to skip the instruction following the current instruction.
+/// This increments the program counter by the length of the next instruction. Note
+/// that the program counter still has to be incremented by the length of the current
+/// instruction afterwards.
+pub(crate) fn skip_next_instruction(state: &mut Chip8) {
+    let next = state.program_counter.wrapping_add(2);
+    let next_is_long = state.variant == Variant::XoChip
+        && state.memory[state.memory_index(next)] == 0xF0
+        && state.memory[state.memory_index(next.wrapping_add(1))] == 0x00;
+
+    increment_program_counter(state);
+    if next_is_long {
         increment_program_counter(state);
     }
 }
 
-/// Convenience function to increment the program counter.
-///
-/// # Panics
-/// In case incrementing the program counter results in a state with an invalid program
-/// counter (i.e., one greater than or equal 4096).
+/// Convenience function to increment the program counter. Note that the resulting
+/// program counter is not validated here. An invalid program counter (i.e., one
+/// pointing to an address from which no complete opcode can be fetched) is reported
+/// when fetching the next instruction.
 pub(crate) fn increment_program_counter(state: &mut Chip8) {
     state.program_counter = state.program_counter.wrapping_add(2);
-    assert!(state.program_counter < 4096);
+}
+
+/// Convenience function to read the memory byte at `address`, wrapping around at the
+/// end of the memory. The read is reported to the watchpoints.
+pub(crate) fn read_memory(state: &mut Chip8, address: u16) -> u8 {
+    let index = state.memory_index(address);
+    state.debugger.on_read(index as u16);
+    state.memory[index]
+}
+
+/// Convenience function to write `value` to the memory byte at `address`, wrapping
+/// around at the end of the memory. The write is reported to the watchpoints, and
+/// invalidates the decoded instructions including the byte.
+pub(crate) fn write_memory(state: &mut Chip8, address: u16, value: u8) {
+    let index = state.memory_index(address);
+    state.debugger.on_write(index as u16);
+    state.memory[index] = value;
+    state.decode_cache.invalidate(index);
+}
+
+/// Convenience function to construct an `ExecutionError::UnknownOpcode` for the opcode
+/// at the current program counter. Note that this must be called before the program
+/// counter is modified by the instruction that is being executed.
+pub(crate) fn unknown_opcode(state: &Chip8) -> ExecutionError {
+    let address = state.program_counter;
+    ExecutionError::UnknownOpcode(
+        address,
+        [
+            state.memory[state.memory_index(address)],
+            state.memory[state.memory_index(address.wrapping_add(1))],
+        ],
+    )
+}
+
+/// Convenience function to return an `ExecutionError::UnknownOpcode` for the opcode at
+/// the current program counter unless the machine is of the XO-CHIP variant.
+pub(crate) fn require_xo_chip(state: &Chip8) -> Result<(), ExecutionError> {
+    if state.variant == Variant::XoChip {
+        Ok(())
+    } else {
+        Err(unknown_opcode(state))
+    }
+}
+
+/// Returns the bitmask selecting the output pin in column `x` of a row of output pins.
+/// The leftmost pin of a row is its most significant bit.
+pub(crate) fn pixel_mask(x: usize) -> u128 {
+    1 << (127 - x)
+}
+
+/// Returns the bitmask selecting the first `width` output pins of a row of output pins,
+/// i.e., the pins that are in use at a resolution of the given width.
+pub(crate) fn row_mask(width: usize) -> u128 {
+    !0 << (128 - width)
+}
+
+/// Convenience function to mark the output pins within `rect` as changed. Rectangles
+/// lying within other dirty rectangles are dropped, and once there are more than
+/// `MAX_DIRTY_RECTS` dirty rectangles, they are merged into their bounding rectangle.
+pub(crate) fn mark_dirty(state: &mut Chip8, rect: Rect) {
+    if rect.is_empty() || state.dirty_rects.iter().any(|dirty| dirty.contains(&rect)) {
+        return;
+    }
+    state.dirty_rects.retain(|dirty| !rect.contains(dirty));
+    state.dirty_rects.push(rect);
+    if state.dirty_rects.len() > MAX_DIRTY_RECTS {
+        let region = state
+            .dirty_rects
+            .iter()
+            .fold(rect, |region, dirty| region.union(dirty));
+        state.dirty_rects.clear();
+        state.dirty_rects.push(region);
+    }
+}
+
+/// Convenience function to mark all output pins of the current resolution as changed,
+/// which replaces all dirty rectangles.
+pub(crate) fn mark_display_dirty(state: &mut Chip8) {
+    let (width, height) = state.resolution();
+    state.dirty_rects.clear();
+    state.dirty_rects.push(Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    });
 }
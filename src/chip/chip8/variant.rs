@@ -0,0 +1,41 @@
+use crate::chip::chip8::constants::{
+    CHIP8_MEMORY_SIZE, CHIP8_PROGRAM_OFFSET, XO_CHIP_MEMORY_SIZE, XO_CHIP_PLANES,
+};
+
+/// Represents the variants of the machine supported by the emulator. The variant
+/// determines the size of the memory, the number of bitplanes of the display, and
+/// which instructions are available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    /// The CHIP-8 including the SUPER-CHIP 1.1 extensions. It has 4KB of memory
+    /// and a single bitplane.
+    #[default]
+    Chip8,
+
+    /// The XO-CHIP. It has 64KB of memory, two bitplanes, and supports the XO-CHIP
+    /// instructions in addition to the CHIP-8 and SUPER-CHIP 1.1 instructions.
+    XoChip,
+}
+
+impl Variant {
+    /// Returns the size of the memory in bytes.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Variant::Chip8 => CHIP8_MEMORY_SIZE,
+            Variant::XoChip => XO_CHIP_MEMORY_SIZE,
+        }
+    }
+
+    /// Returns the maximum size of a program in bytes.
+    pub fn max_program_size(&self) -> usize {
+        self.memory_size() - CHIP8_PROGRAM_OFFSET as usize
+    }
+
+    /// Returns the number of bitplanes of the display.
+    pub fn planes(&self) -> usize {
+        match self {
+            Variant::Chip8 => 1,
+            Variant::XoChip => XO_CHIP_PLANES,
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::chip::audio::{AudioSink, SquareWave, Tone};
+use crate::chip::scheduler::FRAME_RATE;
+
+/// The maximum number of frames buffered for playback. Frames played while the
+/// buffer is full (e.g., in turbo mode) are dropped to keep the latency low.
+const MAX_BUFFERED_FRAMES: u32 = 4;
+
+/// Keeps the output stream of the default audio device (e.g., ALSA on Linux)
+/// open. The sound stops once it is dropped. Note that the stream is not `Send`
+/// on all platforms, so it has to stay on the thread that opened it.
+pub struct CpalOutput {
+    _stream: cpal::Stream,
+}
+
+/// A sink playing the sound on the default audio device.
+pub struct CpalSink {
+    synthesizer: SquareWave,
+    samples: Vec<i16>,
+
+    /// The samples waiting to be played by the output stream.
+    buffer: Arc<Mutex<VecDeque<i16>>>,
+}
+
+impl CpalOutput {
+    /// Opens an output stream on the default audio device, and returns it along
+    /// with a sink feeding it. Returns a description of the problem if there is no
+    /// suitable device.
+    pub fn open() -> Result<(CpalOutput, CpalSink), String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| "No audio output device available.".to_string())?;
+        let config = device.default_output_config().map_err(|e| e.to_string())?;
+        if config.sample_format() != cpal::SampleFormat::F32 {
+            return Err(format!(
+                "Unsupported sample format {:?}.",
+                config.sample_format()
+            ));
+        }
+        let sample_rate = config.sample_rate().0;
+        let channels = config.channels() as usize;
+
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let stream_buffer = Arc::clone(&buffer);
+        let stream = device
+            .build_output_stream(
+                &config.into(),
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    let mut buffer = stream_buffer.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        let sample = buffer.pop_front().unwrap_or(0) as f32 / i16::MAX as f32;
+                        for value in frame.iter_mut() {
+                            *value = sample;
+                        }
+                    }
+                },
+                |_| { /* a glitch in the playback is not worth stopping for */ },
+            )
+            .map_err(|e| e.to_string())?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        let sink = CpalSink {
+            synthesizer: SquareWave::new(sample_rate),
+            samples: vec![],
+            buffer,
+        };
+        Ok((CpalOutput { _stream: stream }, sink))
+    }
+}
+
+impl AudioSink for CpalSink {
+    fn play_frame(&mut self, tone: Option<&Tone>) {
+        self.samples.clear();
+        self.synthesizer.synthesize(tone, &mut self.samples);
+        let max_len = (self.synthesizer.sample_rate() / FRAME_RATE * MAX_BUFFERED_FRAMES) as usize;
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.len() + self.samples.len() <= max_len {
+            buffer.extend(self.samples.iter());
+        }
+    }
+}
@@ -0,0 +1,282 @@
+/// A rectangular region of a framebuffer, given by the column and row of its top
+/// left pixel and its size in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A copy of the display output of a chip at its current resolution. Each pixel
+/// holds the bitplanes it is set in, where bit `i` corresponds to bitplane `i`, and
+/// the pixels are laid out row by row. Additionally, the framebuffer carries the
+/// rectangles that changed since the output was last displayed (see
+/// `Chip::clear_dirty_rects`), so frontends can redraw only what changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    planes: usize,
+    pixels: Vec<u8>,
+    dirty_rects: Vec<Rect>,
+}
+
+impl Rect {
+    /// Returns whether the rectangle does not contain any pixel.
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Returns whether `other` lies completely within the rectangle.
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.x + other.width <= self.x + self.width
+            && other.y + other.height <= self.y + self.height
+    }
+
+    /// Returns the smallest rectangle containing both the rectangle and `other`.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+impl Framebuffer {
+    /// Creates a framebuffer of the given `width` and `height` with `planes`
+    /// bitplanes. All pixels are cleared, and there are no dirty rectangles.
+    ///
+    /// # Panics
+    /// In case `planes` is 0 or larger than 8.
+    pub fn new(width: usize, height: usize, planes: usize) -> Self {
+        assert!((1..=8).contains(&planes));
+        Framebuffer {
+            width,
+            height,
+            planes,
+            pixels: vec![0; width * height],
+            dirty_rects: vec![],
+        }
+    }
+
+    /// Sets the rectangles that changed since the output was last displayed.
+    pub fn with_dirty_rects(mut self, dirty_rects: Vec<Rect>) -> Self {
+        self.dirty_rects = dirty_rects;
+        self
+    }
+
+    /// Returns the width in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the number of bitplanes.
+    pub fn planes(&self) -> usize {
+        self.planes
+    }
+
+    /// Returns the rectangles that changed since the output was last displayed.
+    /// The rectangles may overlap, and there are none if nothing changed.
+    pub fn dirty_rects(&self) -> &[Rect] {
+        &self.dirty_rects
+    }
+
+    /// Returns the smallest rectangle containing all dirty rectangles, or `None`
+    /// if nothing changed.
+    pub fn dirty_region(&self) -> Option<Rect> {
+        let (first, rest) = self.dirty_rects.split_first()?;
+        Some(rest.iter().fold(*first, |region, rect| region.union(rect)))
+    }
+
+    /// Returns all pixels, laid out row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Returns the bitplanes the pixel in column `x` and row `y` is set in.
+    ///
+    /// # Panics
+    /// In case `x` or `y` are outside of the framebuffer.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        assert!(x < self.width && y < self.height);
+        self.pixels[x + y * self.width]
+    }
+
+    /// Returns whether the pixel in column `x` and row `y` is set in the given
+    /// bitplane.
+    ///
+    /// # Panics
+    /// In case `plane` does not refer to a bitplane of the framebuffer, or in case
+    /// `x` or `y` are outside of the framebuffer.
+    pub fn is_set(&self, plane: usize, x: usize, y: usize) -> bool {
+        assert!(plane < self.planes);
+        self.pixel(x, y) & (1 << plane) != 0
+    }
+
+    /// Sets the bitplanes the pixel in column `x` and row `y` is set in.
+    ///
+    /// # Panics
+    /// In case `x` or `y` are outside of the framebuffer, or in case `value` refers
+    /// to bitplanes the framebuffer does not have.
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: u8) {
+        assert!(x < self.width && y < self.height);
+        assert!((value as usize) < 1 << self.planes);
+        self.pixels[x + y * self.width] = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::chip8::{quirks::Quirks, variant::Variant, Chip8};
+    use crate::chip::Chip;
+
+    #[test]
+    fn test_framebuffer() {
+        let mut state = Chip8::with_variant(Variant::XoChip, Quirks::xo_chip());
+        state
+            .load_program(&[
+                0x00, 0xFF, // 0x200: HIGH
+                0xF3, 0x01, // 0x202: PLANE 3
+                0x60, 0x7E, // 0x204: LD V0, 126
+                0x61, 0x3F, // 0x206: LD V1, 63
+                0xA2, 0x0E, // 0x208: LD I, 0x20E
+                0xD0, 0x11, // 0x20A: DRW V0, V1, 1
+                0x12, 0x0C, // 0x20C: JP 0x20C
+                0xC0, 0x60, // 0x20E: sprite rows of bitplanes 0 and 1
+            ])
+            .expect("Loading program failed");
+        let framebuffer = state.framebuffer();
+        assert_eq!((framebuffer.width(), framebuffer.height()), (64, 32));
+        assert_eq!(framebuffer.planes(), 2);
+        assert_eq!(framebuffer.dirty_region(), None);
+
+        for _ in 0..6 {
+            state.cycle().expect("Cycle failed");
+        }
+        let framebuffer = state.framebuffer();
+        assert_eq!((framebuffer.width(), framebuffer.height()), (128, 64));
+        assert_eq!(framebuffer.pixel(126, 63), 0x1);
+        assert_eq!(framebuffer.pixel(127, 63), 0x3);
+        assert_eq!(framebuffer.pixel(0, 63), 0x2);
+        assert!(framebuffer.is_set(1, 0, 63));
+        assert_eq!(
+            framebuffer
+                .pixels()
+                .iter()
+                .filter(|pixel| **pixel != 0)
+                .count(),
+            3
+        );
+        assert_eq!(
+            framebuffer.dirty_region(),
+            Some(Rect {
+                x: 0,
+                y: 0,
+                width: 128,
+                height: 64
+            })
+        );
+
+        state.clear_dirty_rects();
+        assert_eq!(state.framebuffer().dirty_region(), None);
+    }
+    #[test]
+    fn test_dirty_rects() {
+        fn rect(x: usize, y: usize, width: usize, height: usize) -> Rect {
+            Rect {
+                x,
+                y,
+                width,
+                height,
+            }
+        }
+
+        let quirks = Quirks {
+            clip_sprites: false,
+            ..Quirks::default()
+        };
+        let mut state = Chip8::new(quirks);
+        state
+            .load_program(&[
+                0x60, 0x3C, // 0x200: LD V0, 60
+                0x61, 0x1F, // 0x202: LD V1, 31
+                0x62, 0x0A, // 0x204: LD V2, 10
+                0x63, 0x0A, // 0x206: LD V3, 10
+                0x64, 0x04, // 0x208: LD V4, 4
+                0x65, 0x03, // 0x20A: LD V5, 3
+                0x66, 0x00, // 0x20C: LD V6, 0
+                0x67, 0x0A, // 0x20E: LD V7, 10
+                0xD0, 0x13, // 0x210: DRW V0, V1, 3
+                0xD2, 0x33, // 0x212: DRW V2, V3, 3
+                0x00, 0xE0, // 0x214: CLS
+                0xD4, 0x42, // 0x216: DRW V4, V4, 2
+                0xD4, 0x41, // 0x218: DRW V4, V4, 1
+                0xD4, 0x54, // 0x21A: DRW V4, V5, 4
+                0xD6, 0x71, // 0x21C: DRW V6, V7, 1
+                0x76, 0x02, // 0x21E: ADD V6, 2
+                0x77, 0x01, // 0x220: ADD V7, 1
+                0x12, 0x1C, // 0x222: JP 0x21C
+            ])
+            .expect("Loading program failed");
+        for _ in 0..8 {
+            state.cycle().expect("Cycle failed");
+        }
+
+        // a sprite wrapping around the bottom right corner covers four rectangles
+        state.clear_dirty_rects();
+        state.cycle().expect("Cycle failed");
+        assert_eq!(
+            state.framebuffer().dirty_rects(),
+            [
+                rect(60, 31, 4, 1),
+                rect(60, 0, 4, 2),
+                rect(0, 31, 4, 1),
+                rect(0, 0, 4, 2)
+            ]
+        );
+
+        state.clear_dirty_rects();
+        state.cycle().expect("Cycle failed");
+        assert_eq!(state.framebuffer().dirty_rects(), [rect(10, 10, 8, 3)]);
+        state.cycle().expect("Cycle failed");
+        assert_eq!(state.framebuffer().dirty_rects(), [rect(0, 0, 64, 32)]);
+
+        // rectangles within others are dropped
+        state.clear_dirty_rects();
+        for _ in 0..3 {
+            state.cycle().expect("Cycle failed");
+        }
+        assert_eq!(state.framebuffer().dirty_rects(), [rect(4, 3, 8, 4)]);
+
+        // too many rectangles are merged into their bounding rectangle
+        let mut sprites = 0;
+        loop {
+            for _ in 0..4 {
+                state.cycle().expect("Cycle failed");
+            }
+            sprites += 1;
+            if state.framebuffer().dirty_rects().len() == 1 {
+                break;
+            }
+            assert!(sprites < 32);
+        }
+        assert!(sprites > 1);
+        assert_eq!(
+            state.framebuffer().dirty_rects(),
+            [rect(0, 3, 2 * (sprites - 1) + 8, sprites + 7)]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::time::{Duration, Instant};
+
+/// The escape sequence enabling the kitty keyboard protocol with key release
+/// reporting. Terminals that do not support the protocol ignore it.
+pub const KITTY_KEYBOARD_ENABLE: &str = "\x1b[>2u";
+
+/// The escape sequence restoring the keyboard protocol that was active before
+/// `KITTY_KEYBOARD_ENABLE` was sent.
+pub const KITTY_KEYBOARD_DISABLE: &str = "\x1b[<u";
+
+/// The time after which keys are released by default if the terminal does not
+/// report key releases.
+pub const DEFAULT_AUTO_RELEASE_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// The kinds of key events reported by the kitty keyboard protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEventKind {
+    Press,
+    Repeat,
+    Release,
+}
+
+/// Parses a key event of the kitty keyboard protocol, which is reported as
+/// `CSI code[:alternates][;modifiers[:kind]] u`. Returns the key, i.e., the
+/// character of the key on the base layout, along with the kind of the event.
+/// Returns `None` if `bytes` are not such an event.
+pub fn parse_kitty_key_event(bytes: &[u8]) -> Option<(char, KeyEventKind)> {
+    let parameters = std::str::from_utf8(bytes)
+        .ok()?
+        .strip_prefix("\x1b[")?
+        .strip_suffix('u')?;
+    let mut parameters = parameters.split(';');
+    let code = parameters.next()?.split(':').next()?.parse().ok()?;
+    let kind = match parameters
+        .next()
+        .map(|modifiers| modifiers.split(':').nth(1))
+    {
+        None | Some(None) | Some(Some("1")) => KeyEventKind::Press,
+        Some(Some("2")) => KeyEventKind::Repeat,
+        Some(Some("3")) => KeyEventKind::Release,
+        Some(Some(_)) => return None,
+    };
+    Some((std::char::from_u32(code)?, kind))
+}
+
+/// Tracks which keys are held. Terminals usually only report key presses, with
+/// repeated presses while a key is held. Hence, a key is released automatically
+/// once it was not pressed for a timeout. As soon as a key release is reported,
+/// the terminal is assumed to report all releases, and keys are only released
+/// when reported.
+pub struct KeyTracker<K> {
+    auto_release_timeout: Duration,
+
+    /// The held keys along with the point in time they were last pressed.
+    held: Vec<(K, Instant)>,
+
+    /// Whether the terminal reported a key release.
+    reports_releases: bool,
+}
+
+impl<K: Copy + PartialEq> KeyTracker<K> {
+    /// Constructs a tracker releasing keys automatically after
+    /// `auto_release_timeout`, unless the terminal reports key releases.
+    pub fn new(auto_release_timeout: Duration) -> Self {
+        KeyTracker {
+            auto_release_timeout,
+            held: vec![],
+            reports_releases: false,
+        }
+    }
+
+    /// Handles a press of `key` at `now`, including repeated presses of a held
+    /// key. Returns whether the key was not held before.
+    pub fn press(&mut self, key: K, now: Instant) -> bool {
+        match self.held.iter_mut().find(|(held, _)| *held == key) {
+            Some((_, pressed)) => {
+                *pressed = now;
+                false
+            }
+            None => {
+                self.held.push((key, now));
+                true
+            }
+        }
+    }
+
+    /// Handles a reported release of `key`. Returns whether the key was held.
+    pub fn release(&mut self, key: K) -> bool {
+        self.reports_releases = true;
+        let len = self.held.len();
+        self.held.retain(|(held, _)| *held != key);
+        self.held.len() != len
+    }
+
+    /// Releases the keys that were not pressed for the timeout at `now`, unless
+    /// the terminal reports key releases. Returns the released keys.
+    pub fn release_expired(&mut self, now: Instant) -> Vec<K> {
+        if self.reports_releases {
+            return vec![];
+        }
+        let timeout = self.auto_release_timeout;
+        let expired: Vec<K> = self
+            .held
+            .iter()
+            .filter(|(_, pressed)| now.saturating_duration_since(*pressed) >= timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        self.held
+            .retain(|(_, pressed)| now.saturating_duration_since(*pressed) < timeout);
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_kitty_key_event() {
+        assert_eq!(
+            parse_kitty_key_event(b"\x1b[119u"),
+            Some(('w', KeyEventKind::Press))
+        );
+        assert_eq!(
+            parse_kitty_key_event(b"\x1b[119;1:2u"),
+            Some(('w', KeyEventKind::Repeat))
+        );
+        assert_eq!(
+            parse_kitty_key_event(b"\x1b[97:65;2:3u"),
+            Some(('a', KeyEventKind::Release))
+        );
+        assert_eq!(parse_kitty_key_event(b"\x1b[119;1:4u"), None);
+        assert_eq!(parse_kitty_key_event(b"\x1b[1;1:3A"), None);
+        assert_eq!(parse_kitty_key_event(b"w"), None);
+    }
+
+    #[test]
+    fn test_key_tracker() {
+        let start = Instant::now();
+        let after = |millis| start + Duration::from_millis(millis);
+        let mut tracker = KeyTracker::new(Duration::from_millis(100));
+
+        // without reported releases, keys are released once they are not repeated
+        assert!(tracker.press(0x5, start));
+        assert!(tracker.press(0x6, after(50)));
+        assert!(!tracker.press(0x5, after(80)));
+        assert!(tracker.release_expired(after(100)).is_empty());
+        assert_eq!(tracker.release_expired(after(150)), vec![0x6]);
+        assert_eq!(tracker.release_expired(after(180)), vec![0x5]);
+
+        // once a release is reported, keys are held until they are released
+        assert!(tracker.press(0x5, after(200)));
+        assert!(!tracker.release(0x6));
+        assert!(tracker.release_expired(after(1000)).is_empty());
+        assert!(tracker.release(0x5));
+        assert!(!tracker.release(0x5));
+    }
+}
@@ -1,12 +1,63 @@
+/// Producing the sound of a chip.
+pub mod audio;
 /// CHIP-8 implementation
 pub mod chip8;
+/// Playing the sound of a chip on an audio device.
+#[cfg(feature = "audio")]
+pub mod cpal_audio;
+/// Reading the display output of a chip.
+pub mod framebuffer;
+/// Tracking the keys held on a keyboard.
+pub mod keyboard;
+/// Recording and replaying the input of a chip.
+pub mod movie;
+/// Going back in time using snapshots of a chip.
+pub mod rewind;
+/// Running a chip in frames at a fixed rate.
+pub mod scheduler;
 
 use cursive::CbSink;
 
+use framebuffer::Framebuffer;
+
 /// Error type for errors that occur during loading the program
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LoadProgramError {
-    ProgramTooLarge(usize),
+    /// The program does not fit into the memory. Holds the size of the
+    /// program and the maximum program size in bytes.
+    ProgramTooLarge(usize, usize),
+}
+
+/// Error type for errors that occur during loading a save state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStateError {
+    /// The save state is malformed or was not created by this emulator.
+    InvalidFormat,
+    /// The save state was created using the given version of the save state
+    /// format, which is not supported.
+    UnsupportedVersion(u16),
+    /// The save state does not match its checksum, i.e., it is corrupted.
+    ChecksumMismatch,
+}
+
+/// Error type for errors that occur during the execution of a program. Once
+/// such an error occurred, the state of the chip is unspecified and the
+/// execution should be halted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionError {
+    /// The opcode at the given address is not supported. Holds the address
+    /// and the raw bytes of the opcode.
+    UnknownOpcode(u16, [u8; 2]),
+    /// A subroutine was called while the stack was already full.
+    StackOverflow,
+    /// A return from a subroutine occurred while the stack was empty.
+    StackUnderflow,
+    /// The program counter points to an address from which no complete
+    /// opcode can be fetched.
+    ProgramCounterOutOfBounds(u16),
+    /// The address of a font character was requested for a value that is
+    /// not a valid hexadecimal digit.
+    InvalidFontDigit(u8),
 }
 
 /// Represents a chip that supports display output via by sending
@@ -18,24 +69,46 @@ pub trait ChipWithCursiveDisplay {
     fn update_ui(&mut self, gfx_sink: &CbSink);
 }
 
+/// Represents a chip that supports a debugger view via sending
+/// instructions to callback sink of the cursive terminal UI
+/// framework.
+pub trait ChipWithCursiveDebugger {
+    /// Gives the implementation the possibility to send instructions to
+    /// update the debugger view to `gfx_sink`.
+    fn update_debugger_ui(&self, gfx_sink: &CbSink);
+}
+
 /// Represents a chip.
 pub trait Chip {
     /// The type used to address input pins
     type PinAddress;
     /// The type used for memory addresses
     type MemoryAddress;
+    /// The type describing why the execution stopped
+    type StopReason;
 
     /// Mutates self in that it loads the program from the given slice and
     /// stores it into the chip's memory.
     fn load_program(&mut self, bytes: &[u8]) -> Result<(), LoadProgramError>;
 
     /// Preforms an execution cycle. It mutates self so that its state
-    /// corresponds to the state after the execution cycle.
-    fn cycle(&mut self);
+    /// corresponds to the state after the execution cycle. Returns an error
+    /// if the program cannot be executed any further.
+    fn cycle(&mut self) -> Result<(), ExecutionError>;
 
-    /// Returns a slice representing the current state of the output
-    /// pins.
-    fn read_output_pins(&self) -> &[bool];
+    /// Decrements the timers of the chip. This is independent of the execution
+    /// cycles and is supposed to be called at 60 Hz (see `scheduler::Scheduler`).
+    fn tick_timers(&mut self);
+
+    /// Returns a copy of the output pins as a framebuffer of the current
+    /// resolution. The output pins are unpacked from the chip's internal
+    /// representation, so this is meant for frontends rather than for being
+    /// called on every cycle.
+    fn framebuffer(&self) -> Framebuffer;
+
+    /// Marks the output pins as displayed, i.e., clears the dirty rectangles
+    /// of the framebuffers returned until the output pins change again.
+    fn clear_dirty_rects(&mut self);
 
     /// Mutates self so that the input pin referenced by `pin` is set
     /// to `value` after calling this method.
@@ -44,15 +117,80 @@ pub trait Chip {
     /// Mutates self so that all input pins are reset after calling this
     /// method.
     fn reset_input_pins(&mut self);
+
+    /// Returns whether the program requested to exit. Once this returns
+    /// `true`, further execution cycles do not modify the state.
+    fn has_exited(&self) -> bool;
+
+    /// Returns the number of subroutine calls that did not return yet. This
+    /// allows debuggers to step over subroutine calls.
+    fn call_depth(&self) -> usize;
+
+    /// Returns a snapshot of the complete state of the chip, which can be
+    /// restored using `load_state`.
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Mutates self so that its state corresponds to the given snapshot, which
+    /// was created by `save_state`. Returns an error and leaves self untouched if
+    /// the snapshot is invalid.
+    fn load_state(&mut self, state: &[u8]) -> Result<(), LoadStateError>;
+
+    /// Returns and clears the reason why the execution stopped, e.g., because a
+    /// breakpoint was hit during the last cycle. Returns `None` if the execution
+    /// did not stop since this method was last called.
+    fn take_stop_reason(&mut self) -> Option<Self::StopReason>;
 }
 
 impl std::fmt::Display for LoadProgramError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            LoadProgramError::ProgramTooLarge(size) => write!(
+            LoadProgramError::ProgramTooLarge(size, max_size) => write!(
+                f,
+                "Program is too large. Maximum program size is {} bytes. Got {} bytes.",
+                max_size, size
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for LoadStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadStateError::InvalidFormat => write!(f, "Invalid save state format."),
+            LoadStateError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported save state version. Got version {}.",
+                version
+            ),
+            LoadStateError::ChecksumMismatch => {
+                write!(
+                    f,
+                    "Save state checksum mismatch. The save state is corrupted."
+                )
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExecutionError::UnknownOpcode(address, bytes) => write!(
+                f,
+                "Unknown opcode {:02X}{:02X} at address {:#05X}.",
+                bytes[0], bytes[1], address
+            ),
+            ExecutionError::StackOverflow => write!(f, "Stack overflow."),
+            ExecutionError::StackUnderflow => write!(f, "Stack underflow."),
+            ExecutionError::ProgramCounterOutOfBounds(address) => write!(
+                f,
+                "Program counter out of bounds. Cannot fetch opcode at address {:#05X}.",
+                address
+            ),
+            ExecutionError::InvalidFontDigit(digit) => write!(
                 f,
-                "Program is too large. Maximum program size is 3584 bytes. Got {} bytes.",
-                size
+                "Invalid font digit. Expected a value between 0x0 and 0xF. Got {:#04X}.",
+                digit
             ),
         }
     }
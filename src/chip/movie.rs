@@ -0,0 +1,404 @@
+use crate::chip::{Chip, ExecutionError, LoadStateError};
+
+/// The magic bytes identifying a movie.
+const MOVIE_MAGIC: [u8; 4] = *b"C8MV";
+
+/// The version of the movie format. It has to be incremented whenever the format
+/// changes.
+const MOVIE_VERSION: u16 = 2;
+
+/// The highest input pin that can be recorded, as chips have 16 input pins.
+const MAX_INPUT_PIN: u8 = 0xF;
+
+/// Represents an external event affecting a chip, i.e., a change of its input
+/// pins or a tick of its timers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// The input pin passed in the enum value was set to the given value.
+    SetInputPin(u8, bool),
+
+    /// All input pins were reset.
+    ResetInputPins,
+
+    /// The timers were ticked.
+    TickTimers,
+}
+
+/// Error type for errors that occur during loading a movie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadMovieError {
+    /// The movie is malformed or was not created by this emulator.
+    InvalidFormat,
+    /// The movie was created using the given version of the movie format, which
+    /// is not supported.
+    UnsupportedVersion(u16),
+}
+
+/// Error type for errors that occur during replaying a movie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The initial state of the movie could not be loaded.
+    InvalidState(LoadStateError),
+    /// The execution failed during the replay.
+    ExecutionError(ExecutionError),
+    /// The final state does not match the recorded final state. Holds the hash
+    /// of the recorded final state and the hash of the replayed final state.
+    Desync(u64, u64),
+}
+
+/// A recording of the input of a chip, which allows to replay a session
+/// deterministically. A movie consists of the state of the chip at the start of
+/// the recording (as created by `Chip::save_state`), the input events (including
+/// the timer ticks, as these depend on the speed of the emulation) along with
+/// the number of cycles executed before each of them, and the number of cycles
+/// and a hash of the state at the end of the recording.
+pub struct Movie {
+    initial_state: Vec<u8>,
+    events: Vec<(u64, InputEvent)>,
+    length: u64,
+    final_hash: u64,
+}
+
+impl InputEvent {
+    /// Applies the event to `chip`.
+    pub fn apply<T>(&self, chip: &mut T)
+    where
+        T: Chip,
+        T::PinAddress: From<u8>,
+    {
+        match *self {
+            InputEvent::SetInputPin(pin, value) => chip.set_input_pin(pin.into(), value),
+            InputEvent::ResetInputPins => chip.reset_input_pins(),
+            InputEvent::TickTimers => chip.tick_timers(),
+        }
+    }
+}
+
+impl Movie {
+    /// Starts recording a movie of a chip whose state is `initial_state`.
+    pub fn new(initial_state: Vec<u8>) -> Self {
+        Movie {
+            initial_state,
+            events: vec![],
+            length: 0,
+            final_hash: state_hash(&[]),
+        }
+    }
+
+    /// Records `event`, which occurred after `cycle` cycles were executed since
+    /// the start of the recording.
+    ///
+    /// # Panics
+    /// In case `cycle` is smaller than the cycle of the previous event.
+    pub fn record(&mut self, cycle: u64, event: InputEvent) {
+        assert!(!matches!(self.events.last(), Some((last, _)) if *last > cycle));
+        self.events.push((cycle, event));
+    }
+
+    /// Ends the recording after `cycles` cycles were executed since the start of
+    /// the recording, where `final_state` is the state of the chip at the end.
+    pub fn finish(&mut self, cycles: u64, final_state: &[u8]) {
+        self.length = cycles;
+        self.final_hash = state_hash(final_state);
+    }
+
+    /// Returns the state of the chip at the start of the recording.
+    pub fn initial_state(&self) -> &[u8] {
+        &self.initial_state
+    }
+
+    /// Returns the recorded events along with the number of cycles executed
+    /// before each of them.
+    pub fn events(&self) -> &[(u64, InputEvent)] {
+        &self.events
+    }
+
+    /// Returns the number of cycles executed during the recording.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns whether `final_state` matches the state at the end of the recording.
+    pub fn verify(&self, final_state: &[u8]) -> bool {
+        state_hash(final_state) == self.final_hash
+    }
+
+    /// Replays the movie on `chip`. The chip is set to the initial state, and the
+    /// recorded number of cycles is executed while feeding the recorded events.
+    /// Returns an error if the replay fails or does not end in the recorded state.
+    pub fn replay<T>(&self, chip: &mut T) -> Result<(), ReplayError>
+    where
+        T: Chip,
+        T::PinAddress: From<u8>,
+    {
+        chip.load_state(&self.initial_state)
+            .map_err(ReplayError::InvalidState)?;
+        let mut events = self.events.iter().peekable();
+        for cycle in 0..=self.length {
+            while let Some((_, event)) = events.next_if(|(at, _)| *at <= cycle) {
+                event.apply(chip);
+            }
+            if cycle < self.length {
+                chip.cycle().map_err(ReplayError::ExecutionError)?;
+            }
+        }
+
+        let hash = state_hash(&chip.save_state());
+        if hash != self.final_hash {
+            return Err(ReplayError::Desync(self.final_hash, hash));
+        }
+        Ok(())
+    }
+
+    /// Serializes the movie. It consists of `MOVIE_MAGIC`, `MOVIE_VERSION`, the
+    /// length and the final hash, the initial state prefixed by its length, and
+    /// the events prefixed by their number. All numbers are big-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&MOVIE_MAGIC);
+        bytes.extend_from_slice(&MOVIE_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&self.length.to_be_bytes());
+        bytes.extend_from_slice(&self.final_hash.to_be_bytes());
+        bytes.extend_from_slice(&(self.initial_state.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.initial_state);
+        bytes.extend_from_slice(&(self.events.len() as u32).to_be_bytes());
+        for (cycle, event) in self.events.iter() {
+            bytes.extend_from_slice(&cycle.to_be_bytes());
+            match event {
+                InputEvent::SetInputPin(pin, value) => {
+                    bytes.extend_from_slice(&[*value as u8, *pin])
+                }
+                InputEvent::ResetInputPins => bytes.extend_from_slice(&[2, 0]),
+                InputEvent::TickTimers => bytes.extend_from_slice(&[3, 0]),
+            }
+        }
+        bytes
+    }
+
+    /// Deserializes a movie created by `to_bytes`. Note that the initial state is
+    /// only validated when the movie is replayed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, LoadMovieError> {
+        let mut reader = Reader { bytes };
+        if reader.take(4)? != MOVIE_MAGIC {
+            return Err(LoadMovieError::InvalidFormat);
+        }
+        let version = u16::from_be_bytes([reader.u8()?, reader.u8()?]);
+        if version != MOVIE_VERSION {
+            return Err(LoadMovieError::UnsupportedVersion(version));
+        }
+        let length = reader.u64()?;
+        let final_hash = reader.u64()?;
+        let initial_state_len = reader.u32()? as usize;
+        let initial_state = reader.take(initial_state_len)?.to_vec();
+
+        let mut events = vec![];
+        for _ in 0..reader.u32()? {
+            let cycle = reader.u64()?;
+            let event = match (reader.u8()?, reader.u8()?) {
+                (0, pin) if pin <= MAX_INPUT_PIN => InputEvent::SetInputPin(pin, false),
+                (1, pin) if pin <= MAX_INPUT_PIN => InputEvent::SetInputPin(pin, true),
+                (2, 0) => InputEvent::ResetInputPins,
+                (3, 0) => InputEvent::TickTimers,
+                _ => return Err(LoadMovieError::InvalidFormat),
+            };
+            if matches!(events.last(), Some((last, _)) if *last > cycle) {
+                return Err(LoadMovieError::InvalidFormat);
+            }
+            events.push((cycle, event));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(LoadMovieError::InvalidFormat);
+        }
+
+        Ok(Movie {
+            initial_state,
+            events,
+            length,
+            final_hash,
+        })
+    }
+}
+
+/// Computes the 64 bit FNV-1a hash of a state.
+fn state_hash(state: &[u8]) -> u64 {
+    state.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Reads big-endian values from a serialized movie.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LoadMovieError> {
+        if self.bytes.len() < len {
+            return Err(LoadMovieError::InvalidFormat);
+        }
+        let (bytes, remaining) = self.bytes.split_at(len);
+        self.bytes = remaining;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadMovieError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadMovieError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadMovieError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+impl std::fmt::Display for LoadMovieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadMovieError::InvalidFormat => write!(f, "Invalid movie format."),
+            LoadMovieError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported movie version. Got version {}.", version)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReplayError::InvalidState(e) => write!(f, "Invalid initial state: {}", e),
+            ReplayError::ExecutionError(e) => write!(f, "{}", e),
+            ReplayError::Desync(expected, actual) => write!(
+                f,
+                "The replay diverged from the recording: expected final state hash {:016x}, got {:016x}.",
+                expected, actual
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::chip8::{quirks::Quirks, variant::Variant, Chip8};
+    use crate::chip::scheduler::Scheduler;
+
+    /// Records a movie of a program that waits for a key and then draws random
+    /// sprites, pressing the key after 10 cycles and releasing it after 20 cycles.
+    fn record_movie() -> (Movie, Vec<u8>) {
+        // LD V1, 5; SKP V1; JP 0x202; RND V0, 0x0F; LD F, V0; DRW V0, V0, 5; JP 0x206
+        let mut state = Chip8::with_seed(Variant::Chip8, Quirks::default(), 3);
+        state
+            .load_program(&[
+                0x61, 0x05, 0xE1, 0x9E, 0x12, 0x02, 0xC0, 0x0F, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06,
+            ])
+            .expect("Loading program failed");
+        let mut movie = Movie::new(state.save_state());
+        for cycle in 0..100 {
+            match cycle {
+                10 => {
+                    state.set_input_pin(0x5, true);
+                    movie.record(cycle, InputEvent::SetInputPin(0x5, true));
+                }
+                20 => {
+                    state.reset_input_pins();
+                    movie.record(cycle, InputEvent::ResetInputPins);
+                }
+                _ => {}
+            }
+            state.cycle().expect("Cycle failed");
+        }
+        let final_state = state.save_state();
+        movie.finish(100, &final_state);
+        (movie, final_state)
+    }
+
+    #[test]
+    fn test_movie_replay() {
+        let (movie, final_state) = record_movie();
+        let movie = Movie::from_bytes(&movie.to_bytes()).expect("Loading movie failed");
+        assert_eq!(movie.length(), 100);
+        assert_eq!(movie.events().len(), 2);
+
+        // the replay starts from the recorded state, regardless of the chip's state
+        let mut state = Chip8::with_variant(Variant::XoChip, Quirks::xo_chip());
+        assert_eq!(movie.replay(&mut state), Ok(()));
+        assert_eq!(state.save_state(), final_state);
+        assert!(movie.verify(&final_state));
+
+        // replaying with different input diverges
+        let mut bytes = movie.to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last - 10] = 0x6;
+        let tampered = Movie::from_bytes(&bytes).expect("Loading movie failed");
+        assert_eq!(
+            tampered.events()[0],
+            (10, InputEvent::SetInputPin(0x6, true))
+        );
+        assert!(matches!(
+            tampered.replay(&mut state),
+            Err(ReplayError::Desync(..))
+        ));
+    }
+
+    #[test]
+    fn test_movie_replays_timer_ticks() {
+        // LD V0, 0x10; LD DT, V0; JP 0x204
+        let mut state = Chip8::with_seed(Variant::Chip8, Quirks::default(), 3);
+        state
+            .load_program(&[0x60, 0x10, 0xF0, 0x15, 0x12, 0x04])
+            .expect("Loading program failed");
+        let mut movie = Movie::new(state.save_state());
+        let mut scheduler = Scheduler::new(4);
+        for cycle in 1..=50 {
+            if scheduler.cycle(&mut state).expect("Cycle failed") {
+                movie.record(cycle, InputEvent::TickTimers);
+            }
+        }
+        assert_eq!(state.timers().0, 0x10 - 12);
+        movie.finish(50, &state.save_state());
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).expect("Loading movie failed");
+        assert_eq!(movie.events().len(), 12);
+        let mut replayed = Chip8::default();
+        assert_eq!(movie.replay(&mut replayed), Ok(()));
+        assert_eq!(replayed.timers().0, 0x10 - 12);
+    }
+
+    #[test]
+    fn test_load_invalid_movie() {
+        let (movie, _) = record_movie();
+        let bytes = movie.to_bytes();
+        assert!(Movie::from_bytes(&bytes).is_ok());
+        assert_eq!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]).err(),
+            Some(LoadMovieError::InvalidFormat)
+        );
+        assert_eq!(
+            Movie::from_bytes(b"garbage").err(),
+            Some(LoadMovieError::InvalidFormat)
+        );
+
+        let mut newer = bytes.clone();
+        newer[5] += 1;
+        assert_eq!(
+            Movie::from_bytes(&newer).err(),
+            Some(LoadMovieError::UnsupportedVersion(MOVIE_VERSION + 1))
+        );
+
+        // input pins beyond 0xF are rejected
+        let mut invalid_pin = bytes;
+        let last = invalid_pin.len() - 1;
+        invalid_pin[last - 10] = 0x10;
+        assert_eq!(
+            Movie::from_bytes(&invalid_pin).err(),
+            Some(LoadMovieError::InvalidFormat)
+        );
+    }
+}
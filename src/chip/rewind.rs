@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+
+/// Represents the differences of a snapshot with respect to the next newer
+/// snapshot in the buffer.
+enum Delta {
+    /// The snapshot is stored as a whole, because its length differs from the
+    /// length of the next newer snapshot.
+    Full(Vec<u8>),
+
+    /// The snapshot is stored as runs of bytes differing from the next newer
+    /// snapshot. Each run holds its offset and the bytes of the older snapshot.
+    Runs(Vec<(usize, Vec<u8>)>),
+}
+
+/// A bounded ring buffer of snapshots created by `Chip::save_state`, which allows
+/// to go back in time. Only the most recent snapshot is stored as a whole, whereas
+/// all older snapshots are stored as deltas with respect to their successor. Once
+/// the buffer is full, the oldest snapshot is dropped whenever a new one is pushed.
+pub struct RewindBuffer {
+    /// The maximum number of snapshots held by the buffer.
+    capacity: usize,
+
+    /// The most recent snapshot.
+    latest: Option<Vec<u8>>,
+
+    /// The deltas of the older snapshots, ordered from oldest to newest.
+    deltas: VecDeque<Delta>,
+}
+
+impl RewindBuffer {
+    /// Creates an empty buffer holding at most `capacity` snapshots.
+    ///
+    /// # Panics
+    /// In case `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        RewindBuffer {
+            capacity,
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of snapshots held by the buffer.
+    pub fn len(&self) -> usize {
+        self.latest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    /// Returns whether the buffer holds no snapshots.
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Pushes `snapshot` as the most recent snapshot, dropping the oldest snapshot
+    /// if the buffer is full.
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            self.deltas.push_back(delta(&snapshot, previous));
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.latest = Some(snapshot);
+    }
+
+    /// Removes and returns the most recent snapshot, or `None` if the buffer is
+    /// empty.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let latest = self.latest.take()?;
+        self.latest = self.deltas.pop_back().map(|delta| match delta {
+            Delta::Full(snapshot) => snapshot,
+            Delta::Runs(runs) => {
+                let mut snapshot = latest.clone();
+                for (offset, bytes) in runs {
+                    snapshot[offset..offset + bytes.len()].copy_from_slice(&bytes);
+                }
+                snapshot
+            }
+        });
+        Some(latest)
+    }
+
+    /// Removes all snapshots from the buffer.
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+}
+
+/// Computes the delta that restores `previous` from `next`.
+fn delta(next: &[u8], previous: Vec<u8>) -> Delta {
+    if next.len() != previous.len() {
+        return Delta::Full(previous);
+    }
+
+    let mut runs = vec![];
+    let mut i = 0;
+    while i < previous.len() {
+        if previous[i] == next[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < previous.len() && previous[i] != next[i] {
+            i += 1;
+        }
+        runs.push((start, previous[start..i].to_vec()));
+    }
+    Delta::Runs(runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::chip8::{quirks::Quirks, variant::Variant, Chip8};
+    use crate::chip::Chip;
+
+    #[test]
+    fn test_rewind_buffer() {
+        // ADD V0, 0x01; LD I, 0x300; LD [I], V0; JP 0x200
+        let mut state = Chip8::with_variant(Variant::XoChip, Quirks::xo_chip());
+        state
+            .load_program(&[0x70, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x00])
+            .expect("Loading program failed");
+        let mut rewind_buffer = RewindBuffer::new(5);
+        let mut snapshots = vec![];
+        for _ in 0..8 {
+            for _ in 0..4 {
+                state.cycle().expect("Cycle failed");
+            }
+            snapshots.push(state.save_state());
+            rewind_buffer.push(state.save_state());
+        }
+        assert_eq!(rewind_buffer.len(), 5);
+
+        // the most recent snapshots are restored in reverse order
+        for snapshot in snapshots.iter().rev().take(5) {
+            let restored = rewind_buffer.pop().expect("Buffer is empty");
+            assert_eq!(&restored, snapshot);
+            state.load_state(&restored).expect("Loading state failed");
+        }
+        assert!(rewind_buffer.is_empty());
+        assert_eq!(rewind_buffer.pop(), None);
+        // the oldest remaining snapshot was taken after four iterations of the loop
+        assert_eq!(state.save_state(), snapshots[3]);
+    }
+
+    #[test]
+    fn test_rewind_buffer_across_variants() {
+        let mut rewind_buffer = RewindBuffer::new(3);
+        let chip8 = Chip8::default().save_state();
+        let xo_chip = Chip8::with_variant(Variant::XoChip, Quirks::xo_chip()).save_state();
+        rewind_buffer.push(chip8.clone());
+        rewind_buffer.push(xo_chip.clone());
+        rewind_buffer.push(chip8.clone());
+        assert_eq!(rewind_buffer.pop(), Some(chip8.clone()));
+        assert_eq!(rewind_buffer.pop(), Some(xo_chip));
+        assert_eq!(rewind_buffer.pop(), Some(chip8));
+    }
+}
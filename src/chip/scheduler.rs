@@ -0,0 +1,179 @@
+use std::time::{Duration, Instant};
+
+use crate::chip::{Chip, ExecutionError};
+
+/// The frequency at which frames are run and the timers are ticked, in Hz.
+pub const FRAME_RATE: u32 = 60;
+
+/// The number of instructions executed per frame by default. At `FRAME_RATE`,
+/// this amounts to about 1000 instructions per second, the speed at which the
+/// emulator ran before frames were introduced.
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 16;
+
+/// The number of frames the frame limiter may fall behind before it gives up on
+/// catching up and restarts from the current point in time.
+const MAX_FRAMES_BEHIND: u32 = 5;
+
+/// Runs a chip in frames of `1 / FRAME_RATE` seconds. Each frame executes a
+/// configurable number of instructions, after which the timers of the chip are
+/// ticked once. Hence, the timers run at exactly 60 Hz of emulated time,
+/// regardless of the speed of the emulated CPU. The frame limiter keeps emulated
+/// time in sync with the wall clock, unless turbo mode is enabled, in which case
+/// frames are run as fast as possible.
+pub struct Scheduler {
+    /// The number of instructions executed per frame.
+    instructions_per_frame: u32,
+
+    /// The number of instructions executed in the current frame so far.
+    cycles_in_frame: u32,
+
+    /// Whether the frame limiter is disabled.
+    turbo: bool,
+
+    /// The point in time at which the next frame is due to end according to the
+    /// frame limiter, or `None` if no frame was limited yet.
+    deadline: Option<Instant>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler executing `instructions_per_frame` instructions per
+    /// frame, with the frame limiter enabled.
+    ///
+    /// # Panics
+    /// In case `instructions_per_frame` is 0.
+    pub fn new(instructions_per_frame: u32) -> Self {
+        assert!(instructions_per_frame > 0);
+        Scheduler {
+            instructions_per_frame,
+            cycles_in_frame: 0,
+            turbo: false,
+            deadline: None,
+        }
+    }
+
+    /// Returns the number of instructions executed per frame.
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    /// Sets the number of instructions executed per frame. In case the current
+    /// frame already executed as many instructions, it ends after the next one.
+    ///
+    /// # Panics
+    /// In case `instructions_per_frame` is 0.
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) {
+        assert!(instructions_per_frame > 0);
+        self.instructions_per_frame = instructions_per_frame;
+    }
+
+    /// Returns whether turbo mode is enabled, i.e., whether the frame limiter is
+    /// disabled.
+    pub fn turbo(&self) -> bool {
+        self.turbo
+    }
+
+    /// Enables or disables turbo mode.
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+    }
+
+    /// Executes a single cycle of `chip`. Once all instructions of the current
+    /// frame were executed, the timers of `chip` are ticked. Returns whether the
+    /// frame ended with this cycle.
+    pub fn cycle<T: Chip>(&mut self, chip: &mut T) -> Result<bool, ExecutionError> {
+        chip.cycle()?;
+        self.cycles_in_frame += 1;
+        if self.cycles_in_frame < self.instructions_per_frame {
+            return Ok(false);
+        }
+        chip.tick_timers();
+        self.cycles_in_frame = 0;
+        Ok(true)
+    }
+
+    /// Executes the remaining cycles of the current frame, including ticking the
+    /// timers of `chip`.
+    pub fn run_frame<T: Chip>(&mut self, chip: &mut T) -> Result<(), ExecutionError> {
+        while !self.cycle(chip)? {}
+        Ok(())
+    }
+
+    /// Blocks until the current frame is due to end, unless turbo mode is
+    /// enabled. Frames are due every `1 / FRAME_RATE` seconds, counted from the
+    /// first limited frame, so frames that took too long are caught up on by not
+    /// waiting after the following ones. In case the emulation fell behind by
+    /// more than a few frames (e.g., because it was paused), the frame limiter
+    /// restarts from the current point in time instead.
+    pub fn wait_for_frame(&mut self) {
+        let now = Instant::now();
+        if self.turbo {
+            self.deadline = None;
+            return;
+        }
+
+        let frame = Duration::from_secs(1) / FRAME_RATE;
+        let deadline = match self.deadline {
+            Some(deadline) if deadline + frame * MAX_FRAMES_BEHIND >= now => deadline,
+            _ => now,
+        };
+        if deadline > now {
+            std::thread::sleep(deadline - now);
+        }
+        self.deadline = Some(deadline + frame);
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new(DEFAULT_INSTRUCTIONS_PER_FRAME)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::chip8::Chip8;
+
+    #[test]
+    fn test_scheduler() {
+        // LD V0, 0xFF; LD DT, V0; JP 0x204
+        let mut state = Chip8::default();
+        state
+            .load_program(&[0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04])
+            .expect("Loading program failed");
+
+        let mut scheduler = Scheduler::new(7);
+        for cycle in 1..=21 {
+            let frame_ended = scheduler.cycle(&mut state).expect("Cycle failed");
+            assert_eq!(frame_ended, cycle % 7 == 0);
+            if cycle >= 2 {
+                assert_eq!(state.timers().0, 0xFF - cycle / 7);
+            }
+        }
+
+        // the timers run at the same rate regardless of the instructions per frame
+        scheduler.set_instructions_per_frame(100);
+        for _ in 0..3 {
+            scheduler.run_frame(&mut state).expect("Cycle failed");
+        }
+        assert_eq!(state.timers().0, 0xFF - 6);
+
+        // the frame limiter keeps frames apart
+        let start = Instant::now();
+        for _ in 0..6 {
+            scheduler.run_frame(&mut state).expect("Cycle failed");
+            scheduler.wait_for_frame();
+        }
+        assert!(start.elapsed() >= Duration::from_secs(5) / FRAME_RATE);
+
+        // turbo mode does not limit frames
+        scheduler.set_turbo(true);
+        let start = Instant::now();
+        for _ in 0..120 {
+            scheduler.run_frame(&mut state).expect("Cycle failed");
+            scheduler.wait_for_frame();
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(state.timers().0, 0xFF - 132);
+    }
+}